@@ -0,0 +1,14 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract ConstructorArgsTest {
+    uint256 x;
+
+    constructor(uint256 _x) {
+        x = _x;
+    }
+
+    function testArgs() public view {
+        require(x == 7, "constructor args not set");
+    }
+}
@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract ConstructorTest {
+    uint256 immutable answer;
+    uint256 x;
+
+    constructor() {
+        answer = 42;
+        x = 42;
+    }
+
+    function testImmutable() public view {
+        require(answer == 42, "immutable not set");
+    }
+
+    function testConstructorState() public view {
+        require(x == 42, "constructor state not set");
+    }
+}
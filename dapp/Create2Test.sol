@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract Counter {
+    uint256 public count;
+}
+
+contract Create2Test {
+    address constant DEPLOYER = 0x4e59b44847b379578588920cA78FbF26c0B4956C;
+
+    function testCreate2() public {
+        (bool ok, bytes memory ret) =
+            DEPLOYER.call(abi.encodePacked(bytes32(uint256(1)), type(Counter).creationCode));
+        require(ok, "deployment failed");
+
+        address counter;
+        uint256 size;
+        assembly {
+            counter := mload(add(ret, 20))
+            size := extcodesize(counter)
+        }
+        require(size > 0, "no code at the returned address");
+    }
+}
@@ -0,0 +1,13 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract GasLimitTest {
+    /// @dev dapp-gas-limit: 30000000
+    function testHighGasLimit() public view {
+        require(gasleft() > 20000000, "gas limit not raised");
+    }
+
+    function testRestoredGasLimit() public view {
+        require(gasleft() < 20000000, "gas limit not restored");
+    }
+}
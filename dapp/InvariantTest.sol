@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract Counter {
+    uint256 public count;
+
+    function increment() public {
+        count++;
+    }
+}
+
+// there is no setUp(), so the state of the counter carries over between the tests
+contract InvariantTest {
+    Counter public counter = new Counter();
+
+    function testIncrement() public {
+        counter.increment();
+    }
+
+    function testIncrementTwice() public {
+        counter.increment();
+        counter.increment();
+    }
+
+    function testNoop() public {}
+
+    function invariantBelowLimit() public view returns (bool) {
+        return counter.count() <= 2;
+    }
+
+    function count() public view returns (uint256) {
+        return counter.count();
+    }
+}
@@ -0,0 +1,21 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+library Math {
+    function add(uint256 a, uint256 b) public pure returns (uint256) {
+        return a + b;
+    }
+}
+
+library Double {
+    function double(uint256 a) public pure returns (uint256) {
+        return Math.add(a, a);
+    }
+}
+
+contract LibraryTest {
+    function testLinked() public pure {
+        require(Math.add(1, 2) == 3, "add");
+        require(Double.double(2) == 4, "double");
+    }
+}
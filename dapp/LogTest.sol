@@ -0,0 +1,16 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract LogTest {
+    event log_named_uint(string key, uint256 val);
+    event Greeted(address indexed who, string greeting);
+
+    function setUp() public {
+        emit log_named_uint("setUp", 1);
+    }
+
+    function testLogs() public {
+        emit Greeted(address(this), "gm");
+        emit log_named_uint("x", 2);
+    }
+}
@@ -0,0 +1,17 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract PayableTest {
+    function testFunded() public view {
+        require(address(this).balance >= 1 ether, "not funded");
+    }
+
+    function testSender() public view {
+        require(msg.sender == address(0x1337), "wrong sender");
+    }
+
+    /// dapp-value: 1000
+    function testPayable() public payable {
+        require(msg.value == 1000, "no value sent");
+    }
+}
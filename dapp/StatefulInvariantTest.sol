@@ -0,0 +1,16 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract StatefulInvariantTest {
+    uint256 public checks;
+
+    function testChecksDoNotChangeState() public view {
+        require(checks == 0, "invariant changed the state");
+    }
+
+    // invariants are called statically, so this fails instead of counting the checks
+    function invariantCountChecks() public returns (bool) {
+        checks++;
+        return true;
+    }
+}
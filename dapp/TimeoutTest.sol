@@ -0,0 +1,17 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity =0.7.6;
+
+contract TimeoutTest {
+    function testLoop() public pure {
+        uint256 i;
+        while (true) {
+            i++;
+        }
+    }
+
+    function testFuzzLoop(uint256 x) public pure {
+        while (true) {
+            x++;
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{error::Result, DappError};
+
+use ethers::types::{Address, U256};
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+/// How the addresses of the deployed contracts get picked when they are not overridden
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressScheme {
+    /// Keep the address derived by CREATE from the deployer and its nonce
+    Create,
+    /// Consecutive addresses starting at the provided one, in deployment order
+    Sequential(Address),
+}
+
+impl AddressScheme {
+    /// Where sequential addresses start by default, well above the precompiles
+    pub const SEQUENTIAL_START: u64 = 0x1000;
+}
+
+impl Default for AddressScheme {
+    fn default() -> Self {
+        AddressScheme::Create
+    }
+}
+
+impl FromStr for AddressScheme {
+    type Err = DappError;
+
+    /// Parses `create`, `sequential` or `sequential:<start address>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, start) = match s.split_once(':') {
+            Some((scheme, start)) => (scheme, Some(start)),
+            None => (s, None),
+        };
+        Ok(match (scheme.to_lowercase().as_str(), start) {
+            ("create", None) => AddressScheme::Create,
+            ("sequential", None) => {
+                AddressScheme::Sequential(Address::from_low_u64_be(Self::SEQUENTIAL_START))
+            }
+            ("sequential", Some(start)) => AddressScheme::Sequential(
+                start.parse().map_err(|_| DappError::InvalidAddressScheme(s.to_owned()))?,
+            ),
+            _ => return Err(DappError::InvalidAddressScheme(s.to_owned())),
+        })
+    }
+}
+
+/// Picks the addresses the test contracts and libraries get deployed at, so that tests which
+/// hardcode them keep working when contracts get added or reordered
+#[derive(Clone, Debug, Default)]
+pub struct AddressResolver {
+    /// Fixed addresses, keyed by contract name, which take precedence over the scheme
+    pub overrides: HashMap<String, Address>,
+    pub scheme: AddressScheme,
+    /// How many addresses the sequential scheme handed out so far
+    assigned: u64,
+}
+
+impl AddressResolver {
+    pub fn new(scheme: AddressScheme) -> Self {
+        Self { scheme, ..Default::default() }
+    }
+
+    pub fn overrides(mut self, overrides: HashMap<String, Address>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Reads the overrides from a JSON file mapping contract names to addresses
+    pub fn load_overrides(path: impl AsRef<Path>) -> Result<HashMap<String, Address>> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Returns the address the contract should be moved to after being deployed, or `None`
+    /// to keep the one CREATE derived
+    pub fn resolve(&mut self, name: &str) -> Option<Address> {
+        if let Some(address) = self.overrides.get(name) {
+            return Some(*address)
+        }
+        match self.scheme {
+            AddressScheme::Create => None,
+            AddressScheme::Sequential(start) => {
+                let mut bytes = [0u8; 32];
+                (U256::from_big_endian(start.as_bytes()) + self.assigned).to_big_endian(&mut bytes);
+                self.assigned += 1;
+                Some(Address::from_slice(&bytes[12..]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_addresses() {
+        let fixed = Address::repeat_byte(0xaa);
+        let mut overrides = HashMap::new();
+        overrides.insert("Fixed".to_owned(), fixed);
+
+        let mut resolver = AddressResolver::new("sequential".parse().unwrap()).overrides(overrides);
+        assert_eq!(resolver.resolve("Foo"), Some(Address::from_low_u64_be(0x1000)));
+        assert_eq!(resolver.resolve("Fixed"), Some(fixed));
+        assert_eq!(resolver.resolve("Bar"), Some(Address::from_low_u64_be(0x1001)));
+
+        let mut resolver = AddressResolver::default();
+        assert_eq!(resolver.resolve("Foo"), None);
+
+        let scheme: AddressScheme =
+            "sequential:0x00000000000000000000000000000000000000ff".parse().unwrap();
+        assert_eq!(scheme, AddressScheme::Sequential(Address::from_low_u64_be(0xff)));
+        assert!("random".parse::<AddressScheme>().is_err());
+    }
+}
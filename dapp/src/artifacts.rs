@@ -1,7 +1,83 @@
+use crate::{error::Result, DappError};
 use ethers::core::{types::Bytes, utils::CompiledContract};
-use eyre::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// The artifact's file name when the storage path is a directory
+const DEFAULT_OUT_FILE: &str = "dapp.sol.json";
+
+/// Where compiled contracts get persisted to and loaded from, keyed by contract name. Implement
+/// this to e.g. share build outputs between CI workers through a remote cache.
+pub trait ArtifactStorage {
+    /// Loads all the stored contracts
+    fn load(&self) -> Result<HashMap<String, CompiledContract>>;
+
+    /// Stores the contracts, replacing any previously stored ones
+    fn store(&mut self, contracts: &HashMap<String, CompiledContract>) -> Result<()>;
+}
+
+/// Stores the artifacts as a single JSON file. Loading also supports DappTools-style
+/// `.sol.json` artifacts.
+#[derive(Clone, Debug)]
+pub struct FsStorage {
+    path: PathBuf,
+}
+
+impl FsStorage {
+    /// If the path is a directory (or ends with a separator), the artifacts are stored as
+    /// `dapp.sol.json` inside of it
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let is_dir = path.is_dir() || path.to_string_lossy().ends_with(std::path::is_separator);
+        let path = if is_dir { path.join(DEFAULT_OUT_FILE) } else { path };
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl ArtifactStorage for FsStorage {
+    fn load(&self) -> Result<HashMap<String, CompiledContract>> {
+        let file = std::fs::read_to_string(&self.path)
+            .map_err(|source| DappError::MissingArtifact { path: self.path.clone(), source })?;
+        let value = serde_json::from_str::<serde_json::Value>(&file)?;
+        if value.get("contracts").map(|contracts| contracts.is_object()).unwrap_or(false) {
+            serde_json::from_value::<DapptoolsArtifact>(value)?.contracts()
+        } else {
+            Ok(serde_json::from_value(value)?)
+        }
+    }
+
+    fn store(&mut self, contracts: &HashMap<String, CompiledContract>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string(contracts)?)?;
+        Ok(())
+    }
+}
+
+/// Keeps the artifacts in memory, e.g. for running without touching the filesystem
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    contracts: HashMap<String, CompiledContract>,
+}
+
+impl ArtifactStorage for MemoryStorage {
+    fn load(&self) -> Result<HashMap<String, CompiledContract>> {
+        Ok(self.contracts.clone())
+    }
+
+    fn store(&mut self, contracts: &HashMap<String, CompiledContract>) -> Result<()> {
+        self.contracts = contracts.clone();
+        Ok(())
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DapptoolsArtifact {
@@ -88,5 +164,24 @@ mod tests {
         let mut got = contracts.keys().cloned().collect::<Vec<_>>();
         got.sort_by_key(|name| name.to_lowercase());
         assert_eq!(expected, got);
+
+        // the dapptools artifact is also accepted by the filesystem storage
+        let stored = FsStorage::new("testdata/dapp-artifact.json").load().unwrap();
+        assert_eq!(stored.len(), contracts.len());
+    }
+
+    #[test]
+    fn roundtrips_through_fs_storage() {
+        let contracts = FsStorage::new("testdata/dapp-artifact.json").load().unwrap();
+        let dir = std::env::temp_dir().join("dapp-artifacts-test/");
+        let mut storage = FsStorage::new(&dir);
+        assert_eq!(storage.path(), dir.join(DEFAULT_OUT_FILE));
+
+        storage.store(&contracts).unwrap();
+        let loaded = storage.load().unwrap();
+        assert_eq!(loaded.len(), contracts.len());
+        let name = "src/Greeter.sol:Greeter";
+        assert_eq!(loaded[name].runtime_bytecode, contracts[name].runtime_bytecode);
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
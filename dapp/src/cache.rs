@@ -0,0 +1,89 @@
+use crate::TestResult;
+
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
+
+/// The file name of the test cache, stored next to the build artifacts
+pub const TEST_CACHE_FILE: &str = ".dapp-test-cache.json";
+
+/// Results of the last test run that are persisted between runs, so that e.g. only the
+/// previously failed tests can be re-run
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestCache {
+    /// Mapping of contract name to the names of its failed tests
+    pub failures: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl TestCache {
+    /// Collects the failed tests from a test run
+    pub fn from_results(results: &HashMap<String, HashMap<String, TestResult>>) -> Self {
+        let failures = results
+            .iter()
+            .map(|(contract, tests)| {
+                let failed = tests
+                    .iter()
+                    .filter(|(_, result)| !result.success)
+                    .map(|(name, _)| name.clone())
+                    .collect::<BTreeSet<_>>();
+                (contract.clone(), failed)
+            })
+            .filter(|(_, failed)| !failed.is_empty())
+            .collect();
+        Self { failures }
+    }
+
+    /// Reads the cache from disk, returning an empty cache if the file does not exist
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+        let file = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&file)?)
+    }
+
+    /// Writes the cache to disk, creating the parent directory if needed
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_failures() {
+        let result = |success| TestResult { success, ..Default::default() };
+        let mut tests = HashMap::new();
+        tests.insert("testA".to_owned(), result(true));
+        tests.insert("testB".to_owned(), result(false));
+        let mut results = HashMap::new();
+        results.insert("Foo".to_owned(), tests);
+        let mut tests = HashMap::new();
+        tests.insert("testC".to_owned(), result(true));
+        results.insert("Bar".to_owned(), tests);
+
+        let cache = TestCache::from_results(&results);
+        assert_eq!(cache.failures.len(), 1);
+        assert_eq!(cache.failures["Foo"].iter().collect::<Vec<_>>(), vec!["testB"]);
+
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), TEST_CACHE_FILE));
+        cache.save(&path).unwrap();
+        assert_eq!(TestCache::load(&path).unwrap(), cache);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+use ethers::types::Address;
+use evm_adapters::EvmError;
+use std::path::PathBuf;
+
+/// Errors which can occur while compiling and running the tests
+#[derive(thiserror::Error, Debug)]
+pub enum DappError {
+    /// The contracts could not be compiled. The solc driver reports its errors through eyre,
+    /// whose chain of causes is kept as the source.
+    #[error("compilation failed")]
+    Compilation(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+    /// The build artifact which should be used instead of compiling could not be read
+    #[error("could not read artifact at {path:?}: {source}")]
+    MissingArtifact {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The constructor of a test contract did not succeed
+    #[error("could not deploy {name}: {reason}")]
+    Deployment { name: String, reason: String },
+    /// The address resolver picked an address with a special meaning for a contract
+    #[error("cannot deploy {name} at {address:?}, which is reserved for {reserved}")]
+    ReservedAddress { name: String, address: Address, reserved: &'static str },
+    /// The address scheme is neither `create`, `sequential` nor `sequential:<start address>`
+    #[error("invalid address scheme {0}")]
+    InvalidAddressScheme(String),
+    /// There is no deployed contract with this name
+    #[error("could not find contract {0}")]
+    MissingContract(String),
+    /// A test contract which is required to declare a `setUp()` function does not
+    #[error("{0} does not have a setUp() function")]
+    MissingSetup(String),
+    /// Calling the `setUp()` function of a test contract failed
+    #[error("setUp() failed: {0}")]
+    Setup(#[source] EvmError),
+    #[error(transparent)]
+    Evm(#[from] EvmError),
+    #[error(transparent)]
+    Abi(#[from] ethers::abi::Error),
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub(crate) type Result<T, E = DappError> = std::result::Result<T, E>;
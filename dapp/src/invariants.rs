@@ -0,0 +1,76 @@
+//! Global invariants, which get checked after every test so that the test which first breaks
+//! them is flagged even if it does not assert on the corrupted state itself
+
+use ethers::{
+    abi::{Function, Token},
+    types::Address,
+};
+use evm_adapters::Evm;
+
+/// A property of the EVM state which has to hold between tests
+pub enum Invariant<E> {
+    /// An `invariant*()` function of a deployed contract, which is violated if it reverts or
+    /// returns `false`. It gets called statically, so checking it cannot change the state and
+    /// invariants which try to are violated.
+    Solidity { contract: String, address: Address, function: Function },
+    /// A check implemented in Rust, e.g. over the storage of several contracts
+    Rust { name: String, check: Box<dyn Fn(&mut E) -> bool> },
+}
+
+impl<E> Invariant<E> {
+    /// Registers a Rust invariant under the provided name
+    pub fn new(name: impl Into<String>, check: impl Fn(&mut E) -> bool + 'static) -> Self {
+        Invariant::Rust { name: name.into(), check: Box::new(check) }
+    }
+
+    /// The name violations get reported under, `Contract.function` for Solidity invariants
+    pub fn name(&self) -> String {
+        match self {
+            Invariant::Solidity { contract, function, .. } => {
+                format!("{}.{}", contract, function.name)
+            }
+            Invariant::Rust { name, .. } => name.clone(),
+        }
+    }
+
+    /// Evaluates the invariant against the current state
+    pub fn holds<S>(&self, evm: &mut E) -> bool
+    where
+        E: Evm<S>,
+    {
+        match self {
+            Invariant::Solidity { address, function, .. } => {
+                let calldata = match function.encode_input(&[]) {
+                    Ok(calldata) => calldata,
+                    Err(_) => return false,
+                };
+                match evm.call_raw(Address::zero(), *address, calldata.into(), 0.into(), true) {
+                    Ok((retdata, reason, _)) => {
+                        // invariants without a return value only fail by reverting
+                        let returned_false = function
+                            .decode_output(retdata.as_ref())
+                            .map(|tokens| tokens == [Token::Bool(false)])
+                            .unwrap_or(false);
+                        E::is_success(&reason) && !returned_false
+                    }
+                    Err(_) => false,
+                }
+            }
+            Invariant::Rust { check, .. } => check(evm),
+        }
+    }
+}
+
+/// Returns the Solidity invariants declared by a contract, i.e. its `invariant*` functions
+/// which take no arguments
+pub fn solidity_invariants<E>(
+    contract: &str,
+    address: Address,
+    functions: impl IntoIterator<Item = Function>,
+) -> Vec<Invariant<E>> {
+    functions
+        .into_iter()
+        .filter(|function| function.name.starts_with("invariant") && function.inputs.is_empty())
+        .map(|function| Invariant::Solidity { contract: contract.to_owned(), address, function })
+        .collect()
+}
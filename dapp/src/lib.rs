@@ -1,16 +1,44 @@
+mod error;
+pub use error::DappError;
+use error::Result;
+
 mod artifacts;
-pub use artifacts::DapptoolsArtifact;
+pub use artifacts::{ArtifactStorage, DapptoolsArtifact, FsStorage, MemoryStorage};
 
 mod runner;
-pub use runner::{ContractRunner, TestResult};
+pub use runner::{
+    ContractRunner, StorageChange, TestDescription, TestKind, TestResult, TestSummary,
+};
+
+mod addresses;
+pub use addresses::{AddressResolver, AddressScheme};
+
+mod invariants;
+pub use invariants::Invariant;
 
 mod multi_runner;
-pub use multi_runner::{MultiContractRunner, MultiContractRunnerBuilder};
+pub use multi_runner::{MultiContractRunner, MultiContractRunnerBuilder, TestResults};
+
+mod logs;
+pub use logs::{failure_lines, DecodedLog, TestLog};
+
+mod reporter;
+pub use reporter::{
+    JUnitReporter, JsonReporter, NdjsonReporter, PrettyReporter, QuietReporter, Reporter,
+};
+
+mod cache;
+pub use cache::{TestCache, TEST_CACHE_FILE};
+
+mod natspec;
+pub use natspec::Annotations;
 
 mod fuzz;
 
+mod scaffold;
+pub use scaffold::generate_test;
+
 use ethers::abi;
-use eyre::Result;
 
 pub fn decode_revert(error: &[u8]) -> Result<String> {
     Ok(abi::decode(&[abi::ParamType::String], &error[4..])?[0].to_string())
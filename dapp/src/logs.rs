@@ -0,0 +1,308 @@
+use ethers::{
+    abi::{parse_abi, Abi, RawLog, Token},
+    prelude::Lazy,
+    types::{Bytes, H256, I256, U256},
+};
+use serde::{Deserialize, Serialize};
+
+/// The logging events of DSTest
+static DS_TEST_EVENTS: Lazy<Abi> = Lazy::new(|| {
+    parse_abi(&[
+        "event log(string val)",
+        "event logs(bytes val)",
+        "event log_address(address val)",
+        "event log_bytes32(bytes32 val)",
+        "event log_int(int val)",
+        "event log_uint(uint val)",
+        "event log_bytes(bytes val)",
+        "event log_string(string val)",
+        "event log_named_address(string key, address val)",
+        "event log_named_bytes32(string key, bytes32 val)",
+        "event log_named_decimal_int(string key, int val, uint decimals)",
+        "event log_named_decimal_uint(string key, uint val, uint decimals)",
+        "event log_named_int(string key, int val)",
+        "event log_named_uint(string key, uint val)",
+        "event log_named_bytes(string key, bytes val)",
+        "event log_named_string(string key, string val)",
+    ])
+    .expect("invalid DSTest events")
+});
+
+/// An event emitted during a test
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TestLog {
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+    /// The decoded event, if it is declared in the test contract's ABI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<DecodedLog>,
+}
+
+/// An event decoded with the test contract's ABI
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DecodedLog {
+    pub name: String,
+    /// The event's parameter names and their formatted values
+    pub params: Vec<(String, String)>,
+}
+
+impl TestLog {
+    /// Tries decoding the log with the events of the provided ABI
+    pub fn new(log: RawLog, abi: &Abi) -> Self {
+        let decoded = log.topics.get(0).and_then(|topic| {
+            let event =
+                abi.events().find(|event| !event.anonymous && event.signature() == *topic)?;
+            let parsed = event.parse_log(log.clone()).ok()?;
+            let params =
+                parsed.params.into_iter().map(|param| (param.name, format_token(&param.value)));
+            Some(DecodedLog { name: event.name.clone(), params: params.collect() })
+        });
+
+        Self { topics: log.topics, data: log.data.into(), decoded }
+    }
+
+    /// Renders the DSTest logging events (`log`, `log_named_uint`, ...) the way dapptools
+    /// does, e.g. `key: value` for the named ones. `None` for any other event.
+    pub fn ds_test_line(&self) -> Option<String> {
+        let topic = self.topics.get(0)?;
+        let event = DS_TEST_EVENTS.events().find(|event| event.signature() == *topic)?;
+        let log = RawLog { topics: self.topics.clone(), data: self.data.to_vec() };
+        let tokens = event
+            .parse_log(log)
+            .ok()?
+            .params
+            .into_iter()
+            .map(|param| param.value)
+            .collect::<Vec<_>>();
+
+        Some(match (event.name.as_str(), tokens.as_slice()) {
+            (
+                "log_named_decimal_uint",
+                [Token::String(key), Token::Uint(val), Token::Uint(decimals)],
+            ) => format!("{}: {}", key, format_decimal(&val.to_string(), max_decimals(*decimals))),
+            (
+                "log_named_decimal_int",
+                [Token::String(key), Token::Int(val), Token::Uint(decimals)],
+            ) => {
+                let val = I256::from_raw(*val).to_string();
+                format!("{}: {}", key, format_decimal(&val, max_decimals(*decimals)))
+            }
+            (name, [Token::String(key), val]) if name.starts_with("log_named_") => {
+                format!("{}: {}", key, format_token(val))
+            }
+            (_, [val]) => format_token(val),
+            _ => return None,
+        })
+    }
+}
+
+/// Renders the DSTest logs of a failed test, one line per log.
+///
+/// For failed approximate equality assertions (`a ~= b not satisfied`), the delta between the
+/// expected and the actual value is recomputed and displayed along with its percentage of the
+/// expected value, in place of the deltas logged by the assertion.
+pub fn failure_lines(logs: &[TestLog]) -> Vec<String> {
+    let lines = logs.iter().filter_map(TestLog::ds_test_line).collect::<Vec<_>>();
+    let mut out = Vec::with_capacity(lines.len());
+    let mut lines = lines.into_iter().peekable();
+    while let Some(line) = lines.next() {
+        let is_approx = line.starts_with("Error: a ~= b not satisfied");
+        out.push(line);
+        if !is_approx {
+            continue
+        }
+
+        // the assertion logs its values right after the error
+        let (mut expected, mut actual) = (None, None);
+        while let Some((key, value)) = lines
+            .peek()
+            .and_then(|line| line.split_once(": "))
+            .map(|(key, value)| (key.trim().to_owned(), value.to_owned()))
+        {
+            match key.as_str() {
+                "Expected" => expected = Some(value),
+                "Actual" => actual = Some(value),
+                "Max Delta" | "Max % Delta" => {}
+                "Delta" | "% Delta" => {
+                    lines.next();
+                    continue
+                }
+                _ => break,
+            }
+            out.extend(lines.next());
+        }
+
+        if let Some(delta) = expected.zip(actual).and_then(|(e, a)| approx_delta(&e, &a)) {
+            out.push(format!("     Delta: {}", delta));
+        }
+    }
+    out
+}
+
+/// Formats the absolute difference between the two integers and its percentage of the expected
+/// value, e.g. `10 (10%)`
+fn approx_delta(expected: &str, actual: &str) -> Option<String> {
+    let parse = |num: &str| match num.strip_prefix('-') {
+        Some(digits) => U256::from_dec_str(digits).ok().map(|num| (true, num)),
+        None => U256::from_dec_str(num).ok().map(|num| (false, num)),
+    };
+    let (expected_neg, expected) = parse(expected)?;
+    let (actual_neg, actual) = parse(actual)?;
+
+    let delta = if expected_neg != actual_neg {
+        expected.checked_add(actual)?
+    } else if expected > actual {
+        expected - actual
+    } else {
+        actual - expected
+    };
+    if expected.is_zero() {
+        return Some(delta.to_string())
+    }
+
+    // the percentage with 2 decimals
+    let percentage = delta.checked_mul(10_000.into())? / expected;
+    Some(format!("{} ({}%)", delta, format_decimal(&percentage.to_string(), 2)))
+}
+
+/// The most decimal digits a 256-bit integer can have
+const MAX_DECIMALS: u64 = 78;
+
+/// Clamps the decimals of a `log_named_decimal_*` event, which come from the contract, so that
+/// formatting it cannot allocate arbitrarily large strings. Larger values would only prepend
+/// zeros to the fraction, so they get rendered with `MAX_DECIMALS` decimals instead.
+fn max_decimals(decimals: U256) -> u64 {
+    decimals.min(MAX_DECIMALS.into()).as_u64()
+}
+
+/// Formats the decimal string of an integer as a fixed point number with the provided decimals
+fn format_decimal(num: &str, decimals: u64) -> String {
+    let decimals = decimals.min(MAX_DECIMALS) as usize;
+    if decimals == 0 {
+        return num.to_owned()
+    }
+
+    let (sign, digits) = match num.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", num),
+    };
+
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (int, fract) = digits.split_at(digits.len() - decimals);
+    let fract = fract.trim_end_matches('0');
+    if fract.is_empty() {
+        format!("{}{}", sign, int)
+    } else {
+        format!("{}{}.{}", sign, int, fract)
+    }
+}
+
+/// Formats the token the way it would be written in Solidity, i.e. numbers as decimals and
+/// bytes as 0x-prefixed hex
+pub(crate) fn format_token(token: &Token) -> String {
+    match token {
+        Token::Address(address) => format!("{:?}", address),
+        Token::Uint(num) => num.to_string(),
+        Token::Int(num) => I256::from_raw(*num).to_string(),
+        Token::Bool(b) => b.to_string(),
+        Token::String(s) => s.clone(),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => format!("0x{}", hex::encode(bytes)),
+        Token::Array(tokens) | Token::FixedArray(tokens) => {
+            format!("[{}]", tokens.iter().map(format_token).collect::<Vec<_>>().join(", "))
+        }
+        Token::Tuple(tokens) => {
+            format!("({})", tokens.iter().map(format_token).collect::<Vec<_>>().join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{abi::parse_abi, types::U256};
+
+    #[test]
+    fn decodes_known_events() {
+        let abi = parse_abi(&["event log_named_uint(string key, uint val)"]).unwrap();
+        let event = abi.event("log_named_uint").unwrap();
+        let data = ethers::abi::encode(&[Token::String("x".to_owned()), Token::Uint(1.into())]);
+        let log = RawLog { topics: vec![event.signature()], data };
+
+        let decoded = TestLog::new(log.clone(), &abi).decoded.unwrap();
+        assert_eq!(decoded.name, "log_named_uint");
+        assert_eq!(decoded.params, vec![("key".into(), "x".into()), ("val".into(), "1".into())]);
+
+        // unknown events are kept as is
+        let log = TestLog::new(log, &Abi::default());
+        assert!(log.decoded.is_none());
+        assert_eq!(format_token(&Token::Int(U256::MAX)), "-1");
+    }
+
+    #[test]
+    fn renders_approx_eq_failures() {
+        let named = |key: &str, val: u64| {
+            ds_test_log("log_named_uint", &[Token::String(key.to_owned()), Token::Uint(val.into())])
+        };
+        let logs = vec![
+            ds_test_log("log", &[Token::String("Error: a ~= b not satisfied [uint]".to_owned())]),
+            named("  Expected", 200),
+            named("    Actual", 230),
+            named(" Max Delta", 10),
+            named("     Delta", 30),
+            ds_test_log("log", &[Token::String("other".to_owned())]),
+        ];
+        assert_eq!(
+            failure_lines(&logs),
+            vec![
+                "Error: a ~= b not satisfied [uint]",
+                "  Expected: 200",
+                "    Actual: 230",
+                " Max Delta: 10",
+                "     Delta: 30 (15%)",
+                "other",
+            ]
+        );
+
+        assert_eq!(approx_delta("-3", "4").unwrap(), "7 (233.33%)");
+        assert_eq!(approx_delta("0", "4").unwrap(), "4");
+        assert!(approx_delta("1.5", "4").is_none());
+    }
+
+    fn ds_test_log(event: &str, tokens: &[Token]) -> TestLog {
+        let event = DS_TEST_EVENTS.event(event).unwrap();
+        let data = ethers::abi::encode(tokens);
+        TestLog { topics: vec![event.signature()], data: data.into(), decoded: None }
+    }
+
+    #[test]
+    fn renders_ds_test_logs() {
+        let key = || Token::String("amount".to_owned());
+
+        let log = ds_test_log("log", &[Token::String("Error: a == b not satisfied".to_owned())]);
+        assert_eq!(log.ds_test_line().unwrap(), "Error: a == b not satisfied");
+
+        let log = ds_test_log("log_named_uint", &[key(), Token::Uint(42.into())]);
+        assert_eq!(log.ds_test_line().unwrap(), "amount: 42");
+
+        let decimal = [key(), Token::Uint(1_500_000.into()), Token::Uint(6.into())];
+        let log = ds_test_log("log_named_decimal_uint", &decimal);
+        assert_eq!(log.ds_test_line().unwrap(), "amount: 1.5");
+
+        let decimal = [key(), Token::Int(I256::from(-5).into_raw()), Token::Uint(2.into())];
+        let log = ds_test_log("log_named_decimal_int", &decimal);
+        assert_eq!(log.ds_test_line().unwrap(), "amount: -0.05");
+
+        let decimal = [key(), Token::Uint(15.into()), Token::Uint(U256::MAX)];
+        let log = ds_test_log("log_named_decimal_uint", &decimal);
+        assert_eq!(log.ds_test_line().unwrap(), format!("amount: 0.{}15", "0".repeat(76)));
+
+        let log = ds_test_log("log_bytes32", &[Token::FixedBytes(vec![0xab; 32])]);
+        assert_eq!(log.ds_test_line().unwrap(), format!("0x{}", "ab".repeat(32)));
+
+        // other events are not rendered
+        let abi = parse_abi(&["event Transfer(address indexed from, uint amount)"]).unwrap();
+        let topics = vec![abi.event("Transfer").unwrap().signature(), H256::zero()];
+        let log = TestLog { topics, data: vec![0; 32].into(), decoded: None };
+        assert!(log.ds_test_line().is_none());
+    }
+}
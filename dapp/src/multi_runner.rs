@@ -1,17 +1,31 @@
-use crate::{artifacts::DapptoolsArtifact, runner::TestResult, ContractRunner};
-use dapp_solc::SolcBuilder;
-use evm_adapters::Evm;
+use crate::{
+    artifacts::{ArtifactStorage, FsStorage},
+    error::Result,
+    invariants,
+    natspec::{self, Annotations},
+    runner::{self, TestDescription, TestResult, TestSummary},
+    AddressResolver, ContractRunner, DappError, Invariant, Reporter,
+};
+use dapp_solc::{LinkReferences, SolcBuilder};
+use evm_adapters::{
+    BlockEnv, Evm, EvmVersion, GasMetering, CREATE2_DEPLOYER, CREATE2_DEPLOYER_CODE,
+};
 
 use ethers::{
-    types::Address,
-    utils::{keccak256, CompiledContract},
+    abi::Abi,
+    types::{Address, Bytes, H256, U256},
+    utils::CompiledContract,
 };
 
 use proptest::test_runner::TestRunner;
 use regex::Regex;
 
-use eyre::Result;
-use std::{collections::HashMap, marker::PhantomData, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    marker::PhantomData,
+    path::PathBuf,
+    time::Duration,
+};
 
 /// Builder used for instantiating the multi-contract runner
 #[derive(Clone, Debug, Default)]
@@ -27,40 +41,181 @@ pub struct MultiContractRunnerBuilder<'a> {
     pub no_compile: bool,
     /// The fuzzer to be used for running fuzz tests
     pub fuzzer: Option<TestRunner>,
+    /// Only run tests in contracts whose names match this pattern
+    pub contract_pattern: Option<Regex>,
+    /// Only run tests in contracts whose names do not match this pattern
+    pub no_contract_pattern: Option<Regex>,
+    /// Only run tests in contracts whose source paths match this pattern
+    pub path_pattern: Option<Regex>,
+    /// Only run exactly these tests, keyed by contract name
+    pub only_tests: Option<BTreeMap<String, BTreeSet<String>>>,
+    /// Wall-clock time after which a test gets interrupted and reported as timed out
+    pub timeout: Option<Duration>,
+    /// Wall-clock budget of each fuzz test, replacing the fuzzer's fixed number of cases
+    pub fuzz_max_time: Option<Duration>,
+    /// Stop running tests after the first failure
+    pub fail_fast: bool,
+    /// ABI-encoded constructor arguments of the test contracts, keyed by contract name
+    pub constructor_args: HashMap<String, Bytes>,
+    /// Picks the addresses of the deployed test contracts and libraries
+    pub address_resolver: AddressResolver,
+    /// The account which calls the test functions
+    pub sender: Address,
+    /// The ETH balance the sender starts with
+    pub sender_balance: Option<U256>,
+    /// The ETH balance each test contract starts with
+    pub initial_balance: Option<U256>,
+    /// The hard fork to compile the contracts for, which should match the EVM's
+    pub evm_version: Option<EvmVersion>,
+    /// The block the tests run in. EVMs which cannot change their block after instantiation
+    /// only accept the block they already have.
+    pub block_env: Option<BlockEnv>,
+    /// How the gas of the tests gets metered and reported. Defaults to the EVM's.
+    pub gas_metering: Option<GasMetering>,
+    /// Test contracts whose names match this pattern have to declare a `setUp()` function
+    pub require_setup: Option<Regex>,
 }
 
 impl<'a> MultiContractRunnerBuilder<'a> {
     /// Given an EVM, proceeds to return a runner which is able to execute all tests
     /// against that evm
-    pub fn build<E, S>(self, mut evm: E) -> Result<MultiContractRunner<E, S>>
+    pub fn build<E, S>(self, evm: E) -> Result<MultiContractRunner<E, S>>
+    where
+        E: Evm<S>,
+    {
+        let storage = FsStorage::new(self.out_path.clone());
+        self.build_with_storage(evm, &storage)
+    }
+
+    /// Same as [`build`](Self::build), but when skipping compilation the contracts are loaded
+    /// from the provided storage instead of the `out_path`
+    pub fn build_with_storage<E, S>(
+        self,
+        mut evm: E,
+        storage: &dyn ArtifactStorage,
+    ) -> Result<MultiContractRunner<E, S>>
     where
         E: Evm<S>,
     {
         // 1. incremental compilation
         // 2. parallel compilation
         // 3. Hardhat / Truffle-style artifacts
-        let contracts = if self.no_compile {
-            let out_file = std::fs::read_to_string(&self.out_path)?;
-            serde_json::from_str::<DapptoolsArtifact>(&out_file)?.contracts()?
+        let (mut contracts, sources, link_references) = if self.no_compile {
+            let contracts = storage.load()?;
+            // artifact contracts are keyed by `path:name`
+            let sources = contracts
+                .keys()
+                .filter_map(|name| {
+                    name.rsplit_once(':')
+                        .map(|(path, _)| (name.clone(), dapp_solc::normalize_separators(path)))
+                })
+                .collect();
+            // artifacts are only written for linked bytecode
+            (contracts, sources, HashMap::new())
         } else {
-            SolcBuilder::new(self.contracts, self.remappings, self.libraries)?.build_all()?
+            let compilation = |err: eyre::Report| DappError::Compilation(err.into());
+            let mut builder = SolcBuilder::new(self.contracts, self.remappings, self.libraries)
+                .map_err(compilation)?;
+            if let Some(evm_version) = self.evm_version {
+                builder = builder.evm_version(evm_version.solc_evm_version());
+            }
+            let contracts = builder.build_all().map_err(compilation)?;
+            (contracts, builder.sources().clone(), builder.link_references().clone())
         };
 
+        let annotations = annotations(&sources);
+
+        // custom errors may be raised by any contract called during a test
+        let mut errors = Abi::default();
+        for contract in contracts.values() {
+            for (name, declared) in &contract.abi.errors {
+                errors.errors.entry(name.clone()).or_default().extend(declared.iter().cloned());
+            }
+        }
+
+        if let Some(block_env) = self.block_env {
+            evm.set_block_env(block_env)?;
+        }
+        if let Some(gas_metering) = self.gas_metering {
+            evm.set_gas_metering(gas_metering)?;
+        }
+
+        // factories under test may rely on the canonical CREATE2 deployer being available
+        evm.initialize_contracts(vec![(CREATE2_DEPLOYER, CREATE2_DEPLOYER_CODE.clone())]);
+
+        // deploy the test contracts through their constructors, in a fixed order so that
+        // they always end up at the same addresses
         let mut addresses = HashMap::new();
-        let init_state = contracts.iter().map(|(name, compiled)| {
-            // make a fake address for the contract, maybe anti-pattern
-            let addr = Address::from_slice(&keccak256(&compiled.runtime_bytecode)[..20]);
-            addresses.insert(name.clone(), addr);
-            (addr, compiled.runtime_bytecode.clone())
-        });
-        evm.initialize_contracts(init_state);
+        let mut libraries = HashMap::new();
+        let mut resolver = self.address_resolver.clone();
+        let test_contracts = contracts
+            .iter()
+            .filter(|(_, contract)| {
+                // contracts which only declare invariants get deployed to check them
+                contract
+                    .abi
+                    .functions()
+                    .any(|x| x.name.starts_with("test") || x.name.starts_with("invariant"))
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<BTreeSet<_>>();
+        for name in test_contracts {
+            let requires_setup =
+                self.require_setup.as_ref().map(|pattern| pattern.is_match(&name)).unwrap_or(false);
+            if requires_setup && !runner::has_setup(&contracts[&name].abi) {
+                return Err(DappError::MissingSetup(name))
+            }
+            link_contract(
+                &mut evm,
+                &name,
+                &mut contracts,
+                &link_references,
+                &mut libraries,
+                &mut resolver,
+                &mut Vec::new(),
+            )?;
+            let compiled = &contracts[&name];
+            let mut bytecode = compiled.bytecode.to_vec();
+            if let Some(args) = self.constructor_args.get(&name) {
+                bytecode.extend_from_slice(args.as_ref());
+            }
+            let addr = deploy(&mut evm, &name, bytecode.into(), &mut resolver)?;
+            if let Some(balance) = self.initial_balance {
+                evm.set_balance(addr, balance);
+            }
+            addresses.insert(name, addr);
+        }
+        if let Some(balance) = self.sender_balance {
+            evm.set_balance(self.sender, balance);
+        }
+
+        let mut invariants = addresses
+            .iter()
+            .flat_map(|(name, address)| {
+                let functions = contracts[name].abi.functions().cloned().collect::<Vec<_>>();
+                invariants::solidity_invariants(name, *address, functions)
+            })
+            .collect::<Vec<_>>();
+        invariants.sort_by_key(Invariant::name);
 
         Ok(MultiContractRunner {
             contracts,
             addresses,
+            sources,
+            annotations,
+            errors,
             evm,
+            invariants,
             state: PhantomData,
             fuzzer: self.fuzzer,
+            contract_pattern: self.contract_pattern,
+            no_contract_pattern: self.no_contract_pattern,
+            path_pattern: self.path_pattern,
+            only_tests: self.only_tests,
+            timeout: self.timeout,
+            fuzz_max_time: self.fuzz_max_time,
+            fail_fast: self.fail_fast,
+            sender: self.sender,
         })
     }
 
@@ -93,6 +248,230 @@ impl<'a> MultiContractRunnerBuilder<'a> {
         self.no_compile = flag;
         self
     }
+
+    pub fn match_contract(mut self, pattern: Regex) -> Self {
+        self.contract_pattern = Some(pattern);
+        self
+    }
+
+    pub fn no_match_contract(mut self, pattern: Regex) -> Self {
+        self.no_contract_pattern = Some(pattern);
+        self
+    }
+
+    pub fn match_path(mut self, pattern: Regex) -> Self {
+        self.path_pattern = Some(pattern);
+        self
+    }
+
+    /// Restricts the run to the provided tests, keyed by contract name (e.g. the failures
+    /// persisted in a [`TestCache`](crate::TestCache))
+    pub fn only_tests(mut self, tests: BTreeMap<String, BTreeSet<String>>) -> Self {
+        self.only_tests = Some(tests);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn fuzz_max_time(mut self, fuzz_max_time: Duration) -> Self {
+        self.fuzz_max_time = Some(fuzz_max_time);
+        self
+    }
+
+    pub fn fail_fast(mut self, flag: bool) -> Self {
+        self.fail_fast = flag;
+        self
+    }
+
+    /// Sets the ABI-encoded arguments which the test contract gets deployed with, e.g.
+    /// `ethers::abi::encode(&[Token::Uint(7.into())])`
+    pub fn constructor_args(mut self, contract: impl Into<String>, args: Bytes) -> Self {
+        self.constructor_args.insert(contract.into(), args);
+        self
+    }
+
+    pub fn address_resolver(mut self, resolver: AddressResolver) -> Self {
+        self.address_resolver = resolver;
+        self
+    }
+
+    pub fn sender(mut self, sender: Address) -> Self {
+        self.sender = sender;
+        self
+    }
+
+    pub fn sender_balance(mut self, balance: U256) -> Self {
+        self.sender_balance = Some(balance);
+        self
+    }
+
+    pub fn initial_balance(mut self, balance: U256) -> Self {
+        self.initial_balance = Some(balance);
+        self
+    }
+
+    pub fn evm_version(mut self, evm_version: EvmVersion) -> Self {
+        self.evm_version = Some(evm_version);
+        self
+    }
+
+    pub fn block_env(mut self, block_env: BlockEnv) -> Self {
+        self.block_env = Some(block_env);
+        self
+    }
+
+    pub fn gas_metering(mut self, gas_metering: GasMetering) -> Self {
+        self.gas_metering = Some(gas_metering);
+        self
+    }
+
+    /// Requires the test contracts whose names match the pattern to declare a `setUp()`
+    pub fn require_setup(mut self, pattern: Regex) -> Self {
+        self.require_setup = Some(pattern);
+        self
+    }
+}
+
+/// Deploys the contract through its constructor, then moves its code and storage to the
+/// address picked by the resolver, if any. Constructors which store `address(this)` still see
+/// the CREATE address.
+fn deploy<E: Evm<S>, S>(
+    evm: &mut E,
+    name: &str,
+    bytecode: Bytes,
+    resolver: &mut AddressResolver,
+) -> Result<Address> {
+    // only the writes of the constructor get moved along with the code
+    evm.take_storage_writes();
+    let (addr, reason, _) = evm.deploy(Address::zero(), bytecode, 0.into())?;
+    if !E::is_success(&reason) {
+        return Err(DappError::Deployment { name: name.to_owned(), reason: format!("{:?}", reason) })
+    }
+    let target = match resolver.resolve(name) {
+        Some(target) if target != addr => target,
+        _ => return Ok(addr),
+    };
+    if let Some(reserved) = evm_adapters::reserved_address(target) {
+        return Err(DappError::ReservedAddress { name: name.to_owned(), address: target, reserved })
+    }
+
+    let code = evm.code(addr);
+    let written = evm.take_storage_writes().remove(&addr).unwrap_or_default();
+    evm.initialize_contracts(vec![(target, code), (addr, Bytes::default())]);
+    for slot in written.into_keys() {
+        let value = evm.sload(addr, slot);
+        evm.sstore(target, slot, value);
+        evm.sstore(addr, slot, H256::zero());
+    }
+    Ok(target)
+}
+
+/// Fills the library addresses into the bytecode of the contract, deploying the libraries
+/// (and, recursively, the libraries they use) first if needed. `linking` holds the libraries
+/// currently being linked, to detect cycles.
+fn link_contract<E: Evm<S>, S>(
+    evm: &mut E,
+    name: &str,
+    contracts: &mut HashMap<String, CompiledContract>,
+    link_references: &HashMap<String, LinkReferences>,
+    libraries: &mut HashMap<String, Address>,
+    resolver: &mut AddressResolver,
+    linking: &mut Vec<String>,
+) -> Result<()> {
+    let references = match link_references.get(name) {
+        Some(references) => references,
+        None => return Ok(()),
+    };
+    let deployment = |reason: String| DappError::Deployment { name: name.to_owned(), reason };
+    if linking.iter().any(|library| library == name) {
+        return Err(deployment("cyclic library dependency".to_owned()))
+    }
+
+    linking.push(name.to_owned());
+    for library in references.libraries() {
+        if libraries.contains_key(library) {
+            continue
+        }
+        link_contract(evm, library, contracts, link_references, libraries, resolver, linking)?;
+        let bytecode = contracts
+            .get(library)
+            .ok_or_else(|| DappError::MissingContract(library.to_owned()))?
+            .bytecode
+            .clone();
+        let addr = deploy(evm, library, bytecode, resolver)?;
+        libraries.insert(library.to_owned(), addr);
+    }
+    linking.pop();
+
+    let contract =
+        contracts.get_mut(name).ok_or_else(|| DappError::MissingContract(name.to_owned()))?;
+    let address = |library: &str| libraries.get(library).copied();
+    let mut bytecode = contract.bytecode.to_vec();
+    dapp_solc::link(&mut bytecode, &references.bytecode, address)
+        .map_err(|err| deployment(err.to_string()))?;
+    let mut runtime_bytecode = contract.runtime_bytecode.to_vec();
+    dapp_solc::link(&mut runtime_bytecode, &references.runtime_bytecode, address)
+        .map_err(|err| deployment(err.to_string()))?;
+    contract.bytecode = bytecode.into();
+    contract.runtime_bytecode = runtime_bytecode.into();
+    Ok(())
+}
+
+/// Parses the natspec configuration of each contract from its source file
+fn annotations(sources: &HashMap<String, String>) -> HashMap<String, HashMap<String, Annotations>> {
+    let mut files = HashMap::new();
+    sources
+        .iter()
+        .filter_map(|(name, path)| {
+            let parsed = files.entry(path).or_insert_with(|| match std::fs::read_to_string(path) {
+                Ok(source) => natspec::parse(&source),
+                Err(err) => {
+                    tracing::warn!("could not read {} for natspec annotations: {}", path, err);
+                    HashMap::new()
+                }
+            });
+            // artifact contracts are keyed by `path:name`
+            let contract_name = name.rsplit(':').next().unwrap_or(name);
+            parsed.get(contract_name).map(|annotations| (name.clone(), annotations.clone()))
+        })
+        .collect()
+}
+
+/// The results of a run, contract by contract, see [`MultiContractRunner::test_iter`]
+pub struct TestResults<'r, E, S> {
+    runner: &'r mut MultiContractRunner<E, S>,
+    reporter: &'r mut dyn Reporter,
+    pattern: Regex,
+    /// The contracts whose tests did not run yet
+    contracts: std::collections::btree_set::IntoIter<String>,
+    violated: BTreeSet<String>,
+    failed: bool,
+}
+
+impl<'r, E: Evm<S>, S> Iterator for TestResults<'r, E, S> {
+    type Item = Result<(String, HashMap<String, TestResult>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // stop scheduling new contracts after the first failure
+        if self.runner.fail_fast && self.failed {
+            return None
+        }
+        for name in &mut self.contracts {
+            let reporter = &mut *self.reporter;
+            match self.runner.run_contract(&name, &self.pattern, &mut self.violated, reporter) {
+                Ok(results) if results.is_empty() => continue,
+                Ok(results) => {
+                    self.failed |= results.values().any(|result| !result.success);
+                    return Some(Ok((name, results)))
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        None
+    }
 }
 
 pub struct MultiContractRunner<E, S> {
@@ -100,65 +479,228 @@ pub struct MultiContractRunner<E, S> {
     contracts: HashMap<String, CompiledContract>,
     /// Mapping of contract name to the address it's been injected in the EVM state
     addresses: HashMap<String, Address>,
+    /// Mapping of contract name to the path of the file it was declared in
+    sources: HashMap<String, String>,
+    /// Mapping of contract name to the natspec configuration of its functions
+    annotations: HashMap<String, HashMap<String, Annotations>>,
+    /// The custom errors declared by all the contracts
+    errors: Abi,
     /// The EVM instance used in the test runner
     evm: E,
+    /// Global invariants which get checked after every test
+    invariants: Vec<Invariant<E>>,
     fuzzer: Option<TestRunner>,
+    contract_pattern: Option<Regex>,
+    no_contract_pattern: Option<Regex>,
+    path_pattern: Option<Regex>,
+    only_tests: Option<BTreeMap<String, BTreeSet<String>>>,
+    timeout: Option<Duration>,
+    fuzz_max_time: Option<Duration>,
+    fail_fast: bool,
+    sender: Address,
     state: PhantomData<S>,
 }
 
+impl<E, S> MultiContractRunner<E, S> {
+    /// Registers a global invariant, in addition to the `invariant*()` functions of the
+    /// deployed contracts
+    pub fn add_invariant(&mut self, invariant: Invariant<E>) {
+        self.invariants.push(invariant);
+    }
+
+    /// Whether the contract passes the contract name and source path filters
+    fn is_selected(&self, name: &str) -> bool {
+        // artifact contracts are keyed by `path:name`
+        let contract_name = name.rsplit(':').next().unwrap_or(name);
+        let matches_contract =
+            self.contract_pattern.as_ref().map(|re| re.is_match(contract_name)).unwrap_or(true);
+        let excluded =
+            self.no_contract_pattern.as_ref().map(|re| re.is_match(contract_name)).unwrap_or(false);
+        // contracts without a known source (e.g. imported from outside the glob) never match
+        // a path filter
+        let matches_path = self
+            .path_pattern
+            .as_ref()
+            .map(|re| self.sources.get(name).map(|path| re.is_match(path)).unwrap_or(false))
+            .unwrap_or(true);
+
+        let in_only_tests =
+            self.only_tests.as_ref().map(|only| only.contains_key(name)).unwrap_or(true);
+
+        matches_contract && !excluded && matches_path && in_only_tests
+    }
+
+    /// Narrows the test function pattern down to the tests explicitly selected for the
+    /// contract, if any
+    fn test_pattern(&self, name: &str, pattern: &Regex) -> Result<Regex> {
+        Ok(match self.only_tests.as_ref().and_then(|only| only.get(name)) {
+            Some(tests) => {
+                let tests = tests
+                    .iter()
+                    .filter(|test| pattern.is_match(test))
+                    .map(|test| regex::escape(test))
+                    .collect::<Vec<_>>();
+                Regex::new(&format!("^({})$", tests.join("|")))?
+            }
+            None => pattern.clone(),
+        })
+    }
+
+    /// Lists the test functions matching the pattern, grouped by contract name, without
+    /// executing anything
+    pub fn list(&self, pattern: &Regex) -> HashMap<String, Vec<TestDescription>> {
+        self.contracts
+            .iter()
+            .filter(|(name, _)| self.is_selected(name))
+            .map(|(name, contract)| {
+                let mut tests = contract
+                    .abi
+                    .functions()
+                    .filter(|func| pattern.is_match(&func.name))
+                    .filter_map(TestDescription::new)
+                    .collect::<Vec<_>>();
+                tests.sort_by(|a, b| a.name.cmp(&b.name));
+                (name.clone(), tests)
+            })
+            .filter(|(_, tests)| !tests.is_empty())
+            .collect()
+    }
+
+    /// Summarizes the results of a run. Tests which match the pattern but are missing from
+    /// the results (e.g. because the run stopped early) are counted as skipped.
+    pub fn summary(
+        &self,
+        pattern: &Regex,
+        results: &HashMap<String, HashMap<String, TestResult>>,
+    ) -> Result<TestSummary> {
+        let mut skipped = 0;
+        for (name, contract) in self.contracts.iter().filter(|(name, _)| self.is_selected(name)) {
+            let pattern = self.test_pattern(name, pattern)?;
+            let ran = results.get(name);
+            skipped += contract
+                .abi
+                .functions()
+                .filter(|func| func.name.starts_with("test") && pattern.is_match(&func.name))
+                // fuzz tests only get scheduled when there is a fuzzer
+                .filter(|func| func.inputs.is_empty() || self.fuzzer.is_some())
+                .filter(|func| !ran.map(|tests| tests.contains_key(&func.name)).unwrap_or(false))
+                .count();
+        }
+        Ok(TestSummary::new(results, skipped))
+    }
+}
+
 impl<E, S> MultiContractRunner<E, S>
 where
     E: Evm<S>,
 {
     pub fn test(&mut self, pattern: Regex) -> Result<HashMap<String, HashMap<String, TestResult>>> {
+        self.test_with_reporter(pattern, &mut ())
+    }
+
+    /// Runs all tests matching the pattern, notifying the reporter as tests start and finish
+    pub fn test_with_reporter(
+        &mut self,
+        pattern: Regex,
+        reporter: &mut dyn Reporter,
+    ) -> Result<HashMap<String, HashMap<String, TestResult>>> {
+        let results = self.test_iter(pattern, reporter).collect::<Result<HashMap<_, _>>>()?;
+        reporter.on_suite_end(&results);
+        Ok(results)
+    }
+
+    /// Same as [`test_with_reporter`](Self::test_with_reporter), but the tests of each contract
+    /// only run once the iterator gets advanced to it, so that long suites can be consumed as
+    /// they progress without keeping all the results around. Contracts run in name order and
+    /// the ones without any matching test are skipped. The reporter does not get notified
+    /// about the end of the suite.
+    pub fn test_iter<'r>(
+        &'r mut self,
+        pattern: Regex,
+        reporter: &'r mut dyn Reporter,
+    ) -> TestResults<'r, E, S> {
         // NB: We also have access to the contract's abi. When running the test.
         // Can this be useful for decorating the stacktrace during a revert?
-        // TODO: Check if the function starts with `prove` or `invariant`
+        // TODO: Check if the function starts with `prove`
         // Filter out for contracts that have at least 1 test function
-        let contracts = std::mem::take(&mut self.contracts);
-        let tests = contracts
+        let contracts = self
+            .contracts
             .iter()
-            .filter(|(_, contract)| contract.abi.functions().any(|x| x.name.starts_with("test")));
+            .filter(|(name, _)| self.is_selected(name))
+            .filter(|(_, contract)| contract.abi.functions().any(|x| x.name.starts_with("test")))
+            .map(|(name, _)| name.clone())
+            .collect::<BTreeSet<_>>();
 
-        // TODO: Is this pattern OK? We use the memory and then write it back to avoid any
-        // borrow checker issues. Otherwise, we'd need to clone large vectors.
-        let addresses = std::mem::take(&mut self.addresses);
-        let results = tests
-            .into_iter()
-            .map(|(name, contract)| {
-                let address = addresses
-                    .get(name)
-                    .ok_or_else(|| eyre::eyre!("could not find contract address"))?;
-
-                let result = self.run_tests(name, contract, *address, &pattern)?;
-                Ok((name.clone(), result))
-            })
-            .filter_map(|x: Result<_>| x.ok())
-            .filter_map(|(name, res)| if res.is_empty() { None } else { Some((name, res)) })
-            .collect::<HashMap<_, _>>();
+        // invariants which are broken before any test ran are not blamed on the first test
+        let mut violated = BTreeSet::new();
+        for invariant in &self.invariants {
+            if !invariant.holds(&mut self.evm) {
+                tracing::warn!(
+                    "invariant {} does not hold before running the tests",
+                    invariant.name()
+                );
+                violated.insert(invariant.name());
+            }
+        }
 
-        self.contracts = contracts;
-        self.addresses = addresses;
+        TestResults {
+            runner: self,
+            reporter,
+            pattern,
+            contracts: contracts.into_iter(),
+            violated,
+            failed: false,
+        }
+    }
 
-        Ok(results)
+    /// Runs the tests of a contract which match the pattern
+    fn run_contract(
+        &mut self,
+        name: &str,
+        pattern: &Regex,
+        violated_invariants: &mut BTreeSet<String>,
+        reporter: &mut dyn Reporter,
+    ) -> Result<HashMap<String, TestResult>> {
+        let address =
+            *self.addresses.get(name).ok_or_else(|| DappError::MissingContract(name.to_owned()))?;
+        // the runner borrows the EVM mutably, so it cannot borrow the contract from `self`
+        let contract = self
+            .contracts
+            .get(name)
+            .cloned()
+            .ok_or_else(|| DappError::MissingContract(name.to_owned()))?;
+        let pattern = self.test_pattern(name, pattern)?;
+        self.run_tests(name, &contract, address, &pattern, violated_invariants, reporter)
     }
 
-    // The _name field is unused because we only want it for tracing
     #[tracing::instrument(
         name = "contract",
         skip_all,
         err,
-        fields(name = %_name)
+        fields(name = %name)
     )]
     fn run_tests(
         &mut self,
-        _name: &str,
+        name: &str,
         contract: &CompiledContract,
         address: Address,
         pattern: &Regex,
+        violated_invariants: &mut BTreeSet<String>,
+        reporter: &mut dyn Reporter,
     ) -> Result<HashMap<String, TestResult>> {
-        let mut runner = ContractRunner::new(&mut self.evm, contract, address);
-        runner.run_tests(pattern, self.fuzzer.as_mut())
+        let annotations = self.annotations.get(name).cloned().unwrap_or_default();
+        let mut runner = ContractRunner::new(&mut self.evm, contract, address)
+            .sender(self.sender)
+            .timeout(self.timeout)
+            .fuzz_max_time(self.fuzz_max_time)
+            .fail_fast(self.fail_fast)
+            .errors(&self.errors)
+            .annotations(annotations)
+            .invariants(&self.invariants);
+        runner.violated_invariants = std::mem::take(violated_invariants);
+        let results = runner.run_tests_with_reporter(name, pattern, self.fuzzer.as_mut(), reporter);
+        *violated_invariants = runner.violated_invariants;
+        results
     }
 }
 
@@ -178,6 +720,9 @@ mod tests {
         // 3 tests on greeter 1 on gm
         assert_eq!(results["GreeterTest"].len(), 3);
         assert_eq!(results["GmTest"].len(), 1);
+        let summary = runner.summary(&Regex::new(".*").unwrap(), &results).unwrap();
+        assert_eq!(summary, TestSummary { passed: 4, failed: 0, skipped: 0 });
+        assert_eq!(summary.exit_code(), 0);
         for (_, res) in results {
             assert!(res.iter().all(|(_, result)| result.success));
         }
@@ -185,6 +730,57 @@ mod tests {
         let only_gm = runner.test(Regex::new("testGm.*").unwrap()).unwrap();
         assert_eq!(only_gm.len(), 1);
         assert_eq!(only_gm["GmTest"].len(), 1);
+
+        // listing includes the fuzz tests even without a fuzzer
+        let list = runner.list(&Regex::new(".*").unwrap());
+        assert_eq!(list.len(), 2);
+        assert_eq!(list["GreeterTest"].len(), 5);
+        let fuzz = list["GreeterTest"].iter().find(|t| t.name == "testFuzzShrinking").unwrap();
+        assert_eq!(fuzz.kind, crate::TestKind::Fuzz);
+        assert_eq!(fuzz.signature, "testFuzzShrinking(uint256,uint256)");
+    }
+
+    fn test_iter<S, E: Evm<S>>(evm: E) {
+        let mut runner =
+            MultiContractRunnerBuilder::default().contracts("./GreetTest.sol").build(evm).unwrap();
+
+        // the iterator borrows the reporter, so it has to outlive the statement
+        let reporter = &mut ();
+        let mut results = runner.test_iter(Regex::new(".*").unwrap(), reporter);
+        let (name, gm) = results.next().unwrap().unwrap();
+        assert_eq!(name, "GmTest");
+        assert_eq!(gm.len(), 1);
+        let (name, greeter) = results.next().unwrap().unwrap();
+        assert_eq!(name, "GreeterTest");
+        assert_eq!(greeter.len(), 3);
+        assert!(results.next().is_none());
+
+        // contracts without matching tests do not get yielded
+        let names = runner
+            .test_iter(Regex::new("testGm.*").unwrap(), reporter)
+            .map(|result| result.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["GmTest"]);
+    }
+
+    fn test_contract_filters<S, E: Evm<S> + Clone>(evm: E) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./GreetTest.sol")
+            .match_contract(Regex::new("Test$").unwrap())
+            .no_match_contract(Regex::new("^Gm").unwrap())
+            .build(evm.clone())
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("GreeterTest"));
+
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./GreetTest.sol")
+            .match_path(Regex::new("NotGreetTest.sol$").unwrap())
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert!(results.is_empty());
     }
 
     fn test_ds_test_fail<S, E: Evm<S>>(evm: E) {
@@ -195,32 +791,322 @@ mod tests {
         assert!(test.success);
     }
 
+    fn test_constructors<S, E: Evm<S>>(evm: E) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./ConstructorTest.sol")
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        let results = &results["ConstructorTest"];
+        assert_eq!(results.len(), 2);
+        assert!(results.values().all(|result| result.success));
+    }
+
+    fn test_require_setup<S, E: Evm<S> + Clone>(evm: E) {
+        // contracts without a setUp() run their tests unless it is required
+        let err = MultiContractRunnerBuilder::default()
+            .contracts("./ConstructorTest.sol")
+            .require_setup(Regex::new("^Constructor").unwrap())
+            .build(evm.clone())
+            .err()
+            .unwrap();
+        assert!(matches!(err, DappError::MissingSetup(name) if name == "ConstructorTest"));
+
+        // inherited setUp() functions count
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./GreetTest.sol")
+            .require_setup(Regex::new(".*").unwrap())
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert_eq!(results.len(), 2);
+    }
+
+    fn test_constructor_args<S, E: Evm<S>>(evm: E) {
+        let args = ethers::abi::encode(&[ethers::abi::Token::Uint(7.into())]);
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./ConstructorArgsTest.sol")
+            .constructor_args("ConstructorArgsTest", args.into())
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert!(results["ConstructorArgsTest"]["testArgs"].success);
+    }
+
+    fn test_libraries<S, E: Evm<S>>(evm: E) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./LibraryTest.sol")
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert!(results["LibraryTest"]["testLinked"].success);
+    }
+
+    fn test_balances<S, E: Evm<S>>(evm: E) {
+        let ether = U256::exp10(18);
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./PayableTest.sol")
+            .sender(Address::from_low_u64_be(0x1337))
+            .sender_balance(ether)
+            .initial_balance(ether)
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        let results = &results["PayableTest"];
+        assert_eq!(results.len(), 3);
+        assert!(results.values().all(|result| result.success));
+    }
+
+    fn test_gas_limit<S, E: Evm<S>>(evm: E) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./GasLimitTest.sol")
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        let results = &results["GasLimitTest"];
+        assert!(results["testHighGasLimit"].success);
+        assert!(results["testRestoredGasLimit"].success);
+    }
+
+    fn test_reserved_addresses<S, E: Evm<S>>(evm: E) {
+        let mut overrides = HashMap::new();
+        overrides.insert("GreeterTest".to_owned(), evm_adapters::CONSOLE_ADDRESS);
+        let err = MultiContractRunnerBuilder::default()
+            .contracts("./GreetTest.sol")
+            .address_resolver(AddressResolver::default().overrides(overrides))
+            .build(evm)
+            .err()
+            .unwrap();
+        assert!(matches!(err, DappError::ReservedAddress { reserved: "console.log", .. }));
+    }
+
+    fn test_create2_deployer<S, E: Evm<S>>(evm: E) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./Create2Test.sol")
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert!(results["Create2Test"]["testCreate2"].success);
+    }
+
+    fn test_address_resolver<S, E: Evm<S>>(evm: E) {
+        let fixed = Address::repeat_byte(0xaa);
+        let mut overrides = HashMap::new();
+        overrides.insert("Math".to_owned(), fixed);
+        let resolver = AddressResolver::new("sequential".parse().unwrap()).overrides(overrides);
+
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./LibraryTest.sol")
+            .address_resolver(resolver)
+            .build(evm)
+            .unwrap();
+        // `Double` gets the first sequential address, as `Math` is overridden
+        assert_eq!(runner.addresses["LibraryTest"], Address::from_low_u64_be(0x1001));
+
+        // the libraries got linked at their new addresses
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert!(results["LibraryTest"]["testLinked"].success);
+    }
+
+    fn test_evm_version<S, E: Evm<S>>(evm: E, evm_version: EvmVersion) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./GreetTest.sol")
+            .evm_version(evm_version)
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert!(results["GreeterTest"].values().all(|result| result.success));
+    }
+
+    fn test_block_env<S, E: Evm<S>>(evm: E, block_env: BlockEnv) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./GreetTest.sol")
+            .block_env(block_env)
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert!(results["GreeterTest"].values().all(|result| result.success));
+    }
+
+    fn test_gas_metering<S, E: Evm<S>>(evm: E) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./GreetTest.sol")
+            .gas_metering(GasMetering::Disabled)
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        let results = &results["GreeterTest"];
+        assert!(results.values().all(|result| result.success));
+        // unit tests report no gas without metering
+        assert!(results.values().filter_map(|result| result.gas_used).all(|gas| gas == 0));
+    }
+
+    fn test_invariants<S, E: Evm<S>>(evm: E) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./InvariantTest.sol")
+            .build(evm)
+            .unwrap();
+        let address = runner.addresses["InvariantTest"];
+        let count = dapp_utils::get_func("function count() public view returns (uint256)").unwrap();
+        runner.add_invariant(Invariant::new("count below limit", move |evm: &mut E| {
+            evm.call::<U256, _>(Address::zero(), address, &count, (), 0.into())
+                .map(|(count, _, _)| count <= 2.into())
+                .unwrap_or(false)
+        }));
+        runner.add_invariant(Invariant::new("broken", |_: &mut E| false));
+
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        let results = &results["InvariantTest"];
+        assert!(results["testIncrement"].success);
+        let twice = &results["testIncrementTwice"];
+        assert!(!twice.success);
+        assert_eq!(
+            twice.violated_invariants,
+            vec!["InvariantTest.invariantBelowLimit".to_owned(), "count below limit".to_owned()]
+        );
+        // neither the invariants broken by earlier tests nor the ones broken from the start
+        // get blamed on later tests
+        assert!(results["testNoop"].success);
+        assert!(results["testNoop"].violated_invariants.is_empty());
+    }
+
+    fn test_stateful_invariants<S, E: Evm<S>>(evm: E) {
+        let mut runner = MultiContractRunnerBuilder::default()
+            .contracts("./StatefulInvariantTest.sol")
+            .build(evm)
+            .unwrap();
+        let results = runner.test(Regex::new(".*").unwrap()).unwrap();
+        assert!(results["StatefulInvariantTest"]["testChecksDoNotChangeState"].success);
+
+        let address = runner.addresses["StatefulInvariantTest"];
+        let checks =
+            dapp_utils::get_func("function checks() public view returns (uint256)").unwrap();
+        let (checks, _, _) =
+            runner.evm.call::<U256, _>(Address::zero(), address, &checks, (), 0.into()).unwrap();
+        assert_eq!(checks, 0.into());
+    }
+
     mod sputnik {
         use super::*;
         use evm::Config;
         use evm_adapters::sputnik::{
-            helpers::{new_backend, new_vicinity},
+            helpers::{new_backend, new_executor, new_vicinity},
             Executor,
         };
 
         #[test]
         fn test_sputnik_multi_runner() {
-            let config = Config::istanbul();
-            let gas_limit = 12_500_000;
-            let env = new_vicinity();
-            let backend = new_backend(&env, Default::default());
-            let evm = Executor::new(gas_limit, &config, &backend);
-            test_multi_runner(evm);
+            test_multi_runner(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_iter() {
+            test_iter(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_contract_filters() {
+            test_contract_filters(new_executor());
         }
 
         #[test]
         fn test_sputnik_ds_test_fail() {
+            test_ds_test_fail(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_constructors() {
+            test_constructors(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_require_setup() {
+            test_require_setup(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_constructor_args() {
+            test_constructor_args(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_libraries() {
+            test_libraries(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_balances() {
+            test_balances(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_gas_limit() {
+            test_gas_limit(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_reserved_addresses() {
+            test_reserved_addresses(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_create2_deployer() {
+            test_create2_deployer(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_address_resolver() {
+            test_address_resolver(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_evm_version() {
+            // 0.7.6 cannot target Berlin and falls back to Istanbul
+            for evm_version in [EvmVersion::Istanbul, EvmVersion::Berlin] {
+                let config = evm_version.sputnik_cfg().unwrap();
+                let gas_limit = 12_500_000;
+                let env = new_vicinity();
+                let backend = new_backend(&env, Default::default());
+                let evm = Executor::new(gas_limit, &config, &backend);
+                test_evm_version(evm, evm_version);
+            }
+            assert!(EvmVersion::London.sputnik_cfg().is_err());
+        }
+
+        #[test]
+        fn test_sputnik_block_env() {
             let config = Config::istanbul();
             let gas_limit = 12_500_000;
-            let env = new_vicinity();
+            let block_env = BlockEnv { number: 100, timestamp: 1_000, ..Default::default() };
+            let mut env = new_vicinity();
+            block_env.fill_vicinity(&mut env);
             let backend = new_backend(&env, Default::default());
             let evm = Executor::new(gas_limit, &config, &backend);
-            test_ds_test_fail(evm);
+            test_block_env(evm.clone(), block_env);
+
+            // the block of a Sputnik executor is fixed by its backend
+            let err = MultiContractRunnerBuilder::default()
+                .contracts("./GreetTest.sol")
+                .block_env(BlockEnv::default())
+                .build(evm)
+                .err()
+                .unwrap();
+            assert!(matches!(err, DappError::Evm(evm_adapters::EvmError::Unsupported(_))));
+        }
+
+        #[test]
+        fn test_sputnik_gas_metering() {
+            test_gas_metering(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_invariants() {
+            test_invariants(new_executor());
+        }
+
+        #[test]
+        fn test_sputnik_stateful_invariants() {
+            test_stateful_invariants(new_executor());
         }
     }
 
@@ -0,0 +1,137 @@
+//! Per-test configuration through natspec comments, e.g.
+//!
+//! ```solidity
+//! /// dapp-gas-limit: 30000000
+//! function testExpensive() public { ... }
+//!
+//! /// dapp-value: 1000000000000000000
+//! function testDeposit() public payable { ... }
+//! ```
+//!
+//! The annotations may also be written as `@dev` tags, e.g. `/// @dev dapp-gas-limit: 30000000`.
+//! They are read from the source file the test contract is declared in, since the compiler
+//! output we consume does not include the devdoc. Tests inherited from contracts in other files
+//! are therefore not configurable.
+
+use ethers::types::U256;
+use std::collections::HashMap;
+
+/// Configuration of a single test function
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Annotations {
+    /// `dapp-gas-limit`: the gas limit to run the test with
+    pub gas_limit: Option<u64>,
+    /// `dapp-value`: the wei sent along with the call to a payable test
+    pub value: Option<U256>,
+}
+
+impl Annotations {
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "gas-limit" => match value.replace('_', "").parse() {
+                Ok(gas_limit) => self.gas_limit = Some(gas_limit),
+                Err(err) => tracing::warn!("invalid dapp-gas-limit `{}`: {}", value, err),
+            },
+            "value" => match U256::from_dec_str(&value.replace('_', "")) {
+                Ok(wei) => self.value = Some(wei),
+                Err(err) => tracing::warn!("invalid dapp-value `{}`: {:?}", value, err),
+            },
+            other => tracing::warn!("unknown natspec annotation `dapp-{}`", other),
+        }
+    }
+}
+
+/// Parses the `dapp-*` natspec annotations of all functions in the source, keyed by contract
+/// name and then by function name
+pub fn parse(source: &str) -> HashMap<String, HashMap<String, Annotations>> {
+    let mut annotations: HashMap<String, HashMap<String, Annotations>> = HashMap::new();
+    let mut contract = String::new();
+    let mut pending = None;
+
+    for line in source.lines().map(|line| line.trim()) {
+        if let Some(comment) = natspec_comment(line) {
+            let comment = comment.strip_prefix("@dev").map(str::trim_start).unwrap_or(comment);
+            if let Some((key, value)) =
+                comment.strip_prefix("dapp-").and_then(|a| a.split_once(':'))
+            {
+                pending.get_or_insert_with(Annotations::default).set(key.trim(), value.trim());
+            }
+            continue
+        }
+
+        if line.is_empty() || line.starts_with("//") {
+            continue
+        }
+
+        if let Some(name) = declaration(line, &["contract ", "library ", "interface "]) {
+            contract = name.to_owned();
+        } else if let Some(name) = declaration(line, &["function "]) {
+            if let Some(pending) = pending.take() {
+                annotations.entry(contract.clone()).or_default().insert(name.to_owned(), pending);
+            }
+        }
+        // annotations only apply to the declaration right below them
+        pending = None;
+    }
+
+    annotations
+}
+
+/// Returns the contents of a `///` or `/** */` comment line
+fn natspec_comment(line: &str) -> Option<&str> {
+    let comment = line
+        .strip_prefix("///")
+        .or_else(|| line.strip_prefix("/**"))
+        .or_else(|| line.strip_prefix('*'))?;
+    Some(comment.trim_end_matches("*/").trim())
+}
+
+/// Returns the declared name if the line starts with any of the keywords
+fn declaration<'a>(line: &'a str, keywords: &[&str]) -> Option<&'a str> {
+    let line = line.strip_prefix("abstract ").unwrap_or(line);
+    keywords
+        .iter()
+        .find_map(|keyword| line.strip_prefix(keyword))
+        .and_then(|rest| rest.split(|c: char| !c.is_alphanumeric() && c != '_').next())
+        .filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_annotations() {
+        let source = r#"
+contract FooTest {
+    /// dapp-gas-limit: 30_000_000
+    function testExpensive() public {}
+
+    /// dapp-gas-limit: 1
+    uint256 x;
+
+    function testCheap() public {}
+}
+
+contract BarTest {
+    /**
+     * @notice does things
+     * dapp-gas-limit: 100
+     * dapp-value: 1_000
+     */
+    function testExpensive() public payable {}
+
+    /// @dev dapp-gas-limit: 50
+    function testDev() public {}
+}
+"#;
+        let annotations = parse(source);
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations["FooTest"].len(), 1);
+        assert_eq!(annotations["FooTest"]["testExpensive"].gas_limit, Some(30_000_000));
+        assert_eq!(annotations["BarTest"]["testExpensive"].gas_limit, Some(100));
+        assert_eq!(annotations["BarTest"]["testExpensive"].value, Some(1_000.into()));
+        assert_eq!(annotations["FooTest"]["testExpensive"].value, None);
+        assert_eq!(annotations["BarTest"]["testDev"].gas_limit, Some(50));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{TestResult, TestSummary};
+
+use ansi_term::Colour;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Hooks invoked by the [`MultiContractRunner`](crate::MultiContractRunner) while it executes
+/// tests. Implement this to plug a custom UI on top of the runner.
+pub trait Reporter {
+    /// Called before the contracts get compiled
+    fn on_compile_start(&mut self) {}
+
+    /// Called once the contracts have been compiled (or loaded from the artifacts) and deployed
+    fn on_compile_end(&mut self) {}
+
+    /// Called right before a test function gets executed
+    fn on_test_start(&mut self, _contract: &str, _test: &str) {}
+
+    /// Called with the result of each test as soon as it is available
+    fn on_test_result(&mut self, _contract: &str, _test: &str, _result: &TestResult) {}
+
+    /// Called once all the selected tests have been executed
+    fn on_suite_end(&mut self, _results: &HashMap<String, HashMap<String, TestResult>>) {}
+
+    /// Called with the final outcome counts of the run
+    fn on_summary(&mut self, _summary: &TestSummary) {}
+}
+
+/// Reporter which ignores all events
+impl Reporter for () {}
+
+/// Dapptools-style printing
+#[derive(Clone, Debug, Default)]
+pub struct PrettyReporter;
+
+impl Reporter for PrettyReporter {
+    fn on_suite_end(&mut self, results: &HashMap<String, HashMap<String, TestResult>>) {
+        for (i, (contract_name, tests)) in results.iter().enumerate() {
+            if i > 0 {
+                println!()
+            }
+            if !tests.is_empty() {
+                println!("Running {} tests for {}", tests.len(), contract_name);
+            }
+
+            for (name, result) in tests {
+                match result.runs {
+                    Some(runs) => println!("{} {} (runs: {})", status(result), name, runs),
+                    None => println!("{} {} (gas: {})", status(result), name, gas(result)),
+                }
+                print_failure_logs(result);
+            }
+        }
+    }
+}
+
+/// Only prints the failing tests and a one-line summary
+#[derive(Clone, Debug, Default)]
+pub struct QuietReporter;
+
+impl Reporter for QuietReporter {
+    fn on_suite_end(&mut self, results: &HashMap<String, HashMap<String, TestResult>>) {
+        let mut passed = 0;
+        let mut failed = 0;
+        for (contract_name, tests) in results {
+            for (name, result) in tests {
+                if result.success {
+                    passed += 1;
+                } else {
+                    failed += 1;
+                    println!("{} {}:{}", status(result), contract_name, name);
+                    print_failure_logs(result);
+                }
+            }
+        }
+        println!("{} passed, {} failed", passed, failed);
+    }
+}
+
+/// Prints all results as a single JSON object, keyed by contract and test name
+#[derive(Clone, Debug, Default)]
+pub struct JsonReporter;
+
+impl Reporter for JsonReporter {
+    fn on_suite_end(&mut self, results: &HashMap<String, HashMap<String, TestResult>>) {
+        match serde_json::to_string(results) {
+            Ok(res) => println!("{}", res),
+            Err(err) => tracing::error!("could not serialize test results: {}", err),
+        }
+    }
+}
+
+/// Streams one JSON event per line as the run progresses, so that other tools can follow it
+/// without waiting for the whole suite to finish
+#[derive(Clone, Debug, Default)]
+pub struct NdjsonReporter;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    CompileStarted,
+    CompileFinished,
+    TestStarted { contract: &'a str, test: &'a str },
+    TestFinished { contract: &'a str, test: &'a str, result: &'a TestResult },
+    Summary(&'a TestSummary),
+}
+
+impl Event<'_> {
+    fn emit(&self) {
+        match serde_json::to_string(self) {
+            Ok(line) => println!("{}", line),
+            Err(err) => tracing::error!("could not serialize test event: {}", err),
+        }
+    }
+}
+
+impl Reporter for NdjsonReporter {
+    fn on_compile_start(&mut self) {
+        Event::CompileStarted.emit()
+    }
+
+    fn on_compile_end(&mut self) {
+        Event::CompileFinished.emit()
+    }
+
+    fn on_test_start(&mut self, contract: &str, test: &str) {
+        Event::TestStarted { contract, test }.emit()
+    }
+
+    fn on_test_result(&mut self, contract: &str, test: &str, result: &TestResult) {
+        Event::TestFinished { contract, test, result }.emit()
+    }
+
+    fn on_summary(&mut self, summary: &TestSummary) {
+        Event::Summary(summary).emit()
+    }
+}
+
+/// Prints the results in the JUnit XML format understood by most CI systems
+#[derive(Clone, Debug, Default)]
+pub struct JUnitReporter;
+
+impl Reporter for JUnitReporter {
+    fn on_suite_end(&mut self, results: &HashMap<String, HashMap<String, TestResult>>) {
+        println!("{}", junit(results));
+    }
+}
+
+fn junit(results: &HashMap<String, HashMap<String, TestResult>>) -> String {
+    let total = results.values().map(|tests| tests.len()).sum::<usize>();
+    let failures = results.values().flat_map(|tests| tests.values()).filter(|r| !r.success).count();
+
+    let mut out = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    out.push_str(&format!("\n<testsuites tests=\"{}\" failures=\"{}\">\n", total, failures));
+    for (contract_name, tests) in results {
+        let contract_name = escape_xml(contract_name);
+        let failures = tests.values().filter(|r| !r.success).count();
+        out.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+            contract_name,
+            tests.len(),
+            failures
+        ));
+        for (name, result) in tests {
+            let name = escape_xml(name);
+            if result.success {
+                out.push_str(&format!(
+                    "    <testcase classname=\"{}\" name=\"{}\"/>\n",
+                    contract_name, name
+                ));
+            } else {
+                let failure =
+                    if result.timed_out { r#"<failure message="timeout"/>"# } else { "<failure/>" };
+                out.push_str(&format!(
+                    "    <testcase classname=\"{}\" name=\"{}\">\n      {}\n    </testcase>\n",
+                    contract_name, name, failure
+                ));
+            }
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>");
+    out
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn status(result: &TestResult) -> ansi_term::ANSIString<'static> {
+    if result.success {
+        Colour::Green.paint("[PASS]")
+    } else if result.timed_out {
+        Colour::Red.paint("[TIMEOUT]")
+    } else {
+        Colour::Red.paint("[FAIL]")
+    }
+}
+
+/// Prints the warnings of a test below it, along with its revert reason and DSTest logs if it
+/// failed
+fn print_failure_logs(result: &TestResult) {
+    for warning in &result.warnings {
+        println!("    Warning: {}", warning);
+    }
+    if result.success {
+        return
+    }
+    if let Some(reason) = &result.revert_reason {
+        println!("    Revert reason: {}", reason);
+    }
+    for invariant in &result.violated_invariants {
+        println!("    Violated invariant: {}", invariant);
+    }
+    for line in crate::logs::failure_lines(&result.logs) {
+        println!("    {}", line);
+    }
+    if !result.storage_diff.is_empty() {
+        println!("    Storage changes:");
+        for change in &result.storage_diff {
+            println!(
+                "      {:?} slot {:?}: {:?} -> {:?}",
+                change.address, change.slot, change.old, change.new
+            );
+        }
+    }
+}
+
+fn gas(result: &TestResult) -> String {
+    result.gas_used.map(|x| x.to_string()).unwrap_or_else(|| "[fuzztest]".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(success: bool) -> TestResult {
+        TestResult { success, gas_used: Some(1), ..Default::default() }
+    }
+
+    #[test]
+    fn junit_output() {
+        let mut tests = HashMap::new();
+        tests.insert("testFoo".to_owned(), result(false));
+        let mut results = HashMap::new();
+        results.insert("Foo<T>".to_owned(), tests);
+
+        let xml = junit(&results);
+        assert!(xml.contains(r#"<testsuites tests="1" failures="1">"#));
+        assert!(xml.contains(r#"<testsuite name="Foo&lt;T&gt;" tests="1" failures="1">"#));
+        assert!(xml.contains(r#"<testcase classname="Foo&lt;T&gt;" name="testFoo">"#));
+        assert!(xml.contains("<failure/>"));
+    }
+
+    #[test]
+    fn ndjson_events() {
+        let event = Event::TestStarted { contract: "Foo", test: "testFoo" };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"test_started","contract":"Foo","test":"testFoo"}"#
+        );
+
+        let summary = TestSummary { passed: 1, failed: 2, skipped: 3 };
+        assert_eq!(
+            serde_json::to_string(&Event::Summary(&summary)).unwrap(),
+            r#"{"event":"summary","passed":1,"failed":2,"skipped":3}"#
+        );
+
+        let result = result(true);
+        let line = serde_json::to_string(&Event::TestFinished {
+            contract: "Foo",
+            test: "testFoo",
+            result: &result,
+        })
+        .unwrap();
+        assert!(line.starts_with(
+            r#"{"event":"test_finished","contract":"Foo","test":"testFoo","result":{"success":true"#
+        ));
+    }
+}
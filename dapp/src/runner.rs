@@ -1,19 +1,26 @@
 use ethers::{
-    abi::{Function, Token},
+    abi::{Abi, Function, StateMutability, Token},
     prelude::Bytes,
-    types::Address,
+    types::{Address, H256, U256},
     utils::CompiledContract,
 };
 
 use evm_adapters::Evm;
 
-use eyre::Result;
+use crate::{error::Result, logs::TestLog, natspec::Annotations, DappError, Invariant, Reporter};
+
 use regex::Regex;
-use std::{collections::HashMap, time::Instant};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
 
-use proptest::test_runner::{TestError, TestRunner};
+use proptest::test_runner::{Config as FuzzConfig, TestCaseError, TestError, TestRunner};
 use serde::{Deserialize, Serialize};
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CounterExample {
@@ -23,7 +30,42 @@ pub struct CounterExample {
     pub args: Vec<Token>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A storage slot whose value changed during a test call
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageChange {
+    pub address: Address,
+    pub slot: H256,
+    pub old: H256,
+    pub new: H256,
+}
+
+impl StorageChange {
+    /// Returns the net changes of the written storage slots, given the value each of them had
+    /// before it was written to, see [`Evm::take_storage_writes`], and their current value
+    pub fn diff(
+        written: &BTreeMap<Address, BTreeMap<H256, H256>>,
+        current: impl Fn(Address, H256) -> H256,
+    ) -> Vec<Self> {
+        let mut changes = Vec::new();
+        for (address, slots) in written {
+            for (slot, old) in slots {
+                let new = current(*address, *slot);
+                if *old != new {
+                    changes.push(Self { address: *address, slot: *slot, old: *old, new });
+                }
+            }
+        }
+        changes
+    }
+}
+
+/// Whether the contract declares the `setUp()` function which gets called before each test.
+/// Contracts without one run their tests against the state their constructor left.
+pub(crate) fn has_setup(abi: &Abi) -> bool {
+    abi.functions().any(|func| func.name == "setUp" && func.inputs.is_empty())
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct TestResult {
     pub success: bool,
 
@@ -31,6 +73,113 @@ pub struct TestResult {
 
     /// Minimal reproduction test case for failing fuzz tests
     pub counterexample: Option<CounterExample>,
+
+    /// Whether the test ran for longer than the runner's timeout
+    #[serde(default)]
+    pub timed_out: bool,
+
+    /// The events emitted by the test's `setUp()` and test function calls. Not collected for
+    /// fuzz tests.
+    #[serde(default)]
+    pub logs: Vec<TestLog>,
+
+    /// The decoded revert data of a failed unit test, if it reverted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+
+    /// The net storage changes of a unit test's call, excluding the ones done in `setUp()`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub storage_diff: Vec<StorageChange>,
+
+    /// The number of generated cases a fuzz test executed, not counting the shrunk cases of a
+    /// failing test
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runs: Option<u32>,
+
+    /// Problems with the test environment which may explain baffling results, e.g. `setUp()`
+    /// deploying code to a reserved address
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+
+    /// The global invariants which held before the test but not after it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violated_invariants: Vec<String>,
+}
+
+/// The outcome counts of a test run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestSummary {
+    pub passed: usize,
+    pub failed: usize,
+    /// Tests which were selected but did not run, e.g. because of `fail_fast`
+    pub skipped: usize,
+}
+
+impl TestSummary {
+    pub fn new(results: &HashMap<String, HashMap<String, TestResult>>, skipped: usize) -> Self {
+        let (passed, failed) = results.values().flat_map(|tests| tests.values()).fold(
+            (0, 0),
+            |(passed, failed), result| {
+                if result.success {
+                    (passed + 1, failed)
+                } else {
+                    (passed, failed + 1)
+                }
+            },
+        );
+        Self { passed, failed, skipped }
+    }
+
+    /// The process exit code for the run: `0` if no test failed, `1` otherwise
+    pub fn exit_code(&self) -> i32 {
+        if self.failed > 0 {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// The kind of a discovered test function
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestKind {
+    /// `test*` function without arguments
+    Unit,
+    /// `test*` function with arguments, which get generated by the fuzzer
+    Fuzz,
+    /// `invariant*` function
+    Invariant,
+}
+
+/// A test function discovered in a contract's ABI
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestDescription {
+    pub name: String,
+    /// The function's signature, e.g. `testFoo(uint256)`
+    pub signature: String,
+    pub kind: TestKind,
+}
+
+impl TestDescription {
+    /// Returns the description of the function if it is a test function
+    pub fn new(func: &Function) -> Option<Self> {
+        let kind = if func.name.starts_with("test") {
+            if func.inputs.is_empty() {
+                TestKind::Unit
+            } else {
+                TestKind::Fuzz
+            }
+        } else if func.name.starts_with("invariant") {
+            TestKind::Invariant
+        } else {
+            return None
+        };
+
+        let inputs = func.inputs.iter().map(|input| input.kind.to_string()).collect::<Vec<_>>();
+        let signature = format!("{}({})", func.name, inputs.join(","));
+        Some(Self { name: func.name.clone(), signature, kind })
+    }
 }
 
 use std::marker::PhantomData;
@@ -45,13 +194,97 @@ pub struct ContractRunner<'a, S, E> {
     pub evm: Rc<RefCell<&'a mut E>>,
     pub contract: &'a CompiledContract,
     pub address: Address,
+    /// The account which calls the test functions
+    pub sender: Address,
+    /// Wall-clock time after which a test is interrupted and reported as timed out, counting
+    /// from before its `setUp()`.
+    ///
+    /// Calls still running once the deadline passed fail, and fuzz tests stop generating new
+    /// cases. EVMs which cannot interrupt a call (evmodin) only flag the test once its call
+    /// returns.
+    pub timeout: Option<Duration>,
+    /// Wall-clock budget of each fuzz test. When set, the fuzzer keeps generating cases until
+    /// the budget is used up instead of stopping after the configured number of cases.
+    pub fuzz_max_time: Option<Duration>,
+    /// Per-test configuration parsed from natspec comments, keyed by function name
+    pub annotations: HashMap<String, Annotations>,
+    /// Whether to stop running tests after the first failure
+    pub fail_fast: bool,
+    /// ABI declaring the custom errors which revert data gets decoded with. Defaults to the
+    /// contract's ABI.
+    pub errors: Option<&'a Abi>,
+    /// Global invariants which get checked after every test
+    pub invariants: &'a [Invariant<E>],
+    /// The invariants which are already broken, so that only the first test violating an
+    /// invariant gets flagged
+    pub violated_invariants: BTreeSet<String>,
     // need to constrain the trait generic
     state: PhantomData<S>,
 }
 
 impl<'a, S, E> ContractRunner<'a, S, E> {
     pub fn new(evm: &'a mut E, contract: &'a CompiledContract, address: Address) -> Self {
-        Self { evm: Rc::new(RefCell::new(evm)), contract, address, state: PhantomData }
+        Self {
+            evm: Rc::new(RefCell::new(evm)),
+            contract,
+            address,
+            sender: Address::zero(),
+            timeout: None,
+            fuzz_max_time: None,
+            annotations: HashMap::new(),
+            fail_fast: false,
+            errors: None,
+            invariants: &[],
+            violated_invariants: BTreeSet::new(),
+            state: PhantomData,
+        }
+    }
+
+    /// Sets the account which calls the test functions
+    pub fn sender(mut self, sender: Address) -> Self {
+        self.sender = sender;
+        self
+    }
+
+    /// Sets the per-test timeout
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the wall-clock budget of each fuzz test
+    pub fn fuzz_max_time(mut self, fuzz_max_time: Option<Duration>) -> Self {
+        self.fuzz_max_time = fuzz_max_time;
+        self
+    }
+
+    /// Sets whether to stop running tests after the first failure
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Sets the ABI declaring the custom errors to decode revert data with, e.g. one
+    /// combining the errors of all the project's contracts
+    pub fn errors(mut self, errors: &'a Abi) -> Self {
+        self.errors = Some(errors);
+        self
+    }
+
+    /// Sets the global invariants to check after every test
+    pub fn invariants(mut self, invariants: &'a [Invariant<E>]) -> Self {
+        self.invariants = invariants;
+        self
+    }
+
+    /// Sets the per-test natspec configuration
+    pub fn annotations(mut self, annotations: HashMap<String, Annotations>) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    fn is_timed_out(&self, start: Instant) -> bool {
+        self.timeout.map(|timeout| start.elapsed() > timeout).unwrap_or(false)
     }
 }
 
@@ -61,9 +294,27 @@ impl<'a, S, E: Evm<S>> ContractRunner<'a, S, E> {
         &mut self,
         regex: &Regex,
         fuzzer: Option<&mut TestRunner>,
+    ) -> Result<HashMap<String, TestResult>> {
+        self.run_tests_with_reporter("", regex, fuzzer, &mut ())
+    }
+
+    /// Same as [`run_tests`](Self::run_tests), but notifies the reporter about the progress of
+    /// each test, under the provided contract name
+    pub fn run_tests_with_reporter(
+        &mut self,
+        name: &str,
+        regex: &Regex,
+        mut fuzzer: Option<&mut TestRunner>,
+        reporter: &mut dyn Reporter,
     ) -> Result<HashMap<String, TestResult>> {
         let start = Instant::now();
-        let needs_setup = self.contract.abi.functions().any(|func| func.name == "setUp");
+        let needs_setup = has_setup(&self.contract.abi);
+        if !needs_setup {
+            tracing::debug!(
+                "{} has no setUp(), running its tests against the deployed state",
+                name
+            );
+        }
         let test_fns = self
             .contract
             .abi
@@ -73,32 +324,32 @@ impl<'a, S, E: Evm<S>> ContractRunner<'a, S, E> {
             .filter(|func| regex.is_match(&func.name))
             .collect::<Vec<_>>();
 
-        // run all unit tests
-        let unit_tests = test_fns
-            .iter()
-            .filter(|func| func.inputs.is_empty())
-            .map(|func| {
-                let result = self.run_test(func, needs_setup)?;
-                Ok((func.name.clone(), result))
-            })
-            .collect::<Result<HashMap<_, _>>>()?;
+        // run all unit tests before the fuzz tests, which only run if a fuzzer was provided
+        let mut map = HashMap::new();
+        let (unit_tests, fuzz_tests): (Vec<_>, Vec<_>) =
+            test_fns.into_iter().partition(|func| func.inputs.is_empty());
+        for func in unit_tests.into_iter().chain(fuzz_tests) {
+            // stop scheduling new tests after the first failure
+            if self.fail_fast && map.values().any(|result: &TestResult| !result.success) {
+                break
+            }
 
-        let map = if let Some(fuzzer) = fuzzer {
-            let fuzz_tests = test_fns
-                .iter()
-                .filter(|func| !func.inputs.is_empty())
-                .map(|func| {
-                    let result = self.run_fuzz_test(func, needs_setup, fuzzer)?;
-                    Ok((func.name.clone(), result))
-                })
-                .collect::<Result<HashMap<_, _>>>()?;
-
-            let mut map = unit_tests;
-            map.extend(fuzz_tests);
-            map
-        } else {
-            unit_tests
-        };
+            let mut result = if func.inputs.is_empty() {
+                reporter.on_test_start(name, &func.name);
+                self.run_test(func, needs_setup)?
+            } else if let Some(fuzzer) = fuzzer.as_mut() {
+                reporter.on_test_start(name, &func.name);
+                self.run_fuzz_test(func, needs_setup, fuzzer)?
+            } else {
+                continue
+            };
+            result.violated_invariants = self.check_invariants();
+            if !result.violated_invariants.is_empty() {
+                result.success = false;
+            }
+            reporter.on_test_result(name, &func.name, &result);
+            map.insert(func.name.clone(), result);
+        }
 
         if !map.is_empty() {
             let duration = Instant::now().duration_since(start);
@@ -107,31 +358,145 @@ impl<'a, S, E: Evm<S>> ContractRunner<'a, S, E> {
         Ok(map)
     }
 
+    /// Returns the invariants which got violated since the last check
+    pub fn check_invariants(&mut self) -> Vec<String> {
+        let mut evm = self.evm.borrow_mut();
+        let mut violated = Vec::new();
+        for invariant in self.invariants {
+            let name = invariant.name();
+            if !self.violated_invariants.contains(&name) && !invariant.holds(&mut **evm) {
+                tracing::warn!("invariant {} was violated", name);
+                self.violated_invariants.insert(name.clone());
+                violated.push(name);
+            }
+        }
+        violated
+    }
+
+    /// Applies the test's `dapp-gas-limit` annotation, if any, returning the previous gas limit
+    fn set_test_gas_limit(&self, func: &Function) -> u64 {
+        let mut evm = self.evm.borrow_mut();
+        let prev_gas_limit = evm.gas_limit();
+        if let Some(gas_limit) = self.annotations.get(&func.name).and_then(|a| a.gas_limit) {
+            evm.set_gas_limit(gas_limit);
+        }
+        prev_gas_limit
+    }
+
+    /// Warns about code at the addresses reserved for precompiles and cheatcodes, which
+    /// shadows them for the rest of the test
+    fn reserved_address_warnings(&self) -> Vec<String> {
+        let evm = self.evm.borrow();
+        evm_adapters::reserved_addresses()
+            .into_iter()
+            .filter(|(address, _)| !evm.code(*address).as_ref().is_empty())
+            .map(|(address, reserved)| {
+                let warning = format!("{:?} is reserved for {} but has code", address, reserved);
+                tracing::warn!("{}", warning);
+                warning
+            })
+            .collect()
+    }
+
+    /// Returns the test's `dapp-value` annotation, which only payable tests get called with
+    fn test_value(&self, func: &Function) -> U256 {
+        let value = match self.annotations.get(&func.name).and_then(|a| a.value) {
+            Some(value) => value,
+            None => return U256::zero(),
+        };
+        if func.state_mutability == StateMutability::Payable {
+            value
+        } else {
+            tracing::warn!("ignoring the dapp-value of {} since it is not payable", func.name);
+            U256::zero()
+        }
+    }
+
+    /// Runs `f` with the EVM interrupting the calls which are still running once the timeout
+    /// passed, counting from `start`
+    fn with_deadline<T>(&mut self, start: Instant, f: impl FnOnce(&mut Self) -> T) -> T {
+        let deadline = self.timeout.map(|timeout| start + timeout);
+        self.evm.borrow_mut().set_deadline(deadline);
+        let result = f(self);
+        // the invariant checks and later uses of the EVM are not bound by the test's deadline
+        self.evm.borrow_mut().set_deadline(None);
+        result
+    }
+
     #[tracing::instrument(name = "test", skip_all, fields(name = %func.name))]
     pub fn run_test(&mut self, func: &Function, setup: bool) -> Result<TestResult> {
         let start = Instant::now();
+        self.with_deadline(start, |runner| runner.unit_test(func, setup, start))
+    }
+
+    fn unit_test(&mut self, func: &Function, setup: bool, start: Instant) -> Result<TestResult> {
         // the expected result depends on the function name
         // DAppTools' ds-test will not revert inside its `assertEq`-like functions
         // which allows to test multiple assertions in 1 test function while also
         // preserving logs.
         let should_fail = func.name.starts_with("testFail");
+        // drop the logs of earlier calls, e.g. the invariant checks after the previous test
+        self.evm.borrow_mut().take_logs();
         // call the setup function in each test to reset the test's state.
         if setup {
-            self.evm.borrow_mut().setup(self.address)?;
+            self.evm.borrow_mut().setup(self.address).map_err(DappError::Setup)?;
         }
-
-        let (_, reason, gas_used) = self.evm.borrow_mut().call::<(), _>(
-            Address::zero(),
+        let warnings = self.reserved_address_warnings();
+
+        // the writes of `setUp()` are not part of the test's storage diff
+        self.evm.borrow_mut().take_storage_writes();
+        let value = self.test_value(func);
+        let prev_gas_limit = self.set_test_gas_limit(func);
+        // call the test without decoding the output to keep the revert data around
+        let calldata = func.encode_input(&[])?;
+        let res = self.evm.borrow_mut().call_raw(
+            self.sender,
             self.address,
-            func,
-            (),
-            0.into(),
-        )?;
-        let success = self.evm.borrow_mut().check_success(self.address, &reason, should_fail);
+            calldata.into(),
+            value,
+            false,
+        );
+        self.evm.borrow_mut().set_gas_limit(prev_gas_limit);
+        let (retdata, reason, gas_used) = res?;
+        let timed_out = self.is_timed_out(start);
+        let success =
+            !timed_out && self.evm.borrow_mut().check_success(self.address, &reason, should_fail);
+        let revert_reason = if !success && E::is_fail(&reason) && !retdata.as_ref().is_empty() {
+            let errors = self.errors.unwrap_or(&self.contract.abi);
+            Some(
+                dapp_utils::decode_revert(retdata.as_ref(), Some(errors))
+                    .unwrap_or_else(|_| format!("0x{}", hex::encode(&retdata))),
+            )
+        } else {
+            None
+        };
+        let logs = self
+            .evm
+            .borrow_mut()
+            .take_logs()
+            .into_iter()
+            .map(|log| TestLog::new(log, &self.contract.abi))
+            .collect();
+        let storage_diff = {
+            let mut evm = self.evm.borrow_mut();
+            let written = evm.take_storage_writes();
+            StorageChange::diff(&written, |address, slot| evm.sload(address, slot))
+        };
         let duration = Instant::now().duration_since(start);
-        tracing::trace!(?duration, %success, %gas_used);
-
-        Ok(TestResult { success, gas_used: Some(gas_used), counterexample: None })
+        tracing::trace!(?duration, %success, %gas_used, %timed_out);
+
+        Ok(TestResult {
+            success,
+            gas_used: Some(gas_used),
+            counterexample: None,
+            timed_out,
+            logs,
+            revert_reason,
+            storage_diff,
+            runs: None,
+            warnings,
+            violated_invariants: Vec::new(),
+        })
     }
 
     #[tracing::instrument(name = "fuzz-test", skip_all, fields(name = %func.name))]
@@ -140,36 +505,88 @@ impl<'a, S, E: Evm<S>> ContractRunner<'a, S, E> {
         func: &Function,
         setup: bool,
         runner: &mut TestRunner,
+    ) -> Result<TestResult> {
+        let start = Instant::now();
+        self.with_deadline(start, |this| this.fuzz_test(func, setup, runner, start))
+    }
+
+    fn fuzz_test(
+        &mut self,
+        func: &Function,
+        setup: bool,
+        runner: &mut TestRunner,
+        start: Instant,
     ) -> Result<TestResult> {
         // call the setup function in each test to reset the test's state.
         if setup {
-            self.evm.borrow_mut().setup(self.address)?;
+            self.evm.borrow_mut().setup(self.address).map_err(DappError::Setup)?;
         }
+        let warnings = self.reserved_address_warnings();
 
-        let start = Instant::now();
         let should_fail = func.name.starts_with("testFail");
 
         // Get the calldata generation strategy for the function
         let strat = crate::fuzz::fuzz_calldata(func);
 
+        // With a time budget the number of cases is only bounded by the deadline, so we run a
+        // fork of the provided fuzzer which does not stop after its configured cases
+        let mut budget_runner;
+        let runner = match self.fuzz_max_time {
+            Some(_) => {
+                let config = FuzzConfig { cases: u32::MAX, ..runner.config().clone() };
+                budget_runner = TestRunner::new_with_rng(config, runner.new_rng());
+                &mut budget_runner
+            }
+            None => runner,
+        };
+        let budget_exhausted =
+            || self.fuzz_max_time.map(|budget| start.elapsed() > budget).unwrap_or(false);
+        let runs = Cell::new(0);
+        // after the first failure the runner only re-executes shrunk cases
+        let shrinking = Cell::new(false);
+
         // Run the strategy
+        let value = self.test_value(func);
+        let prev_gas_limit = self.set_test_gas_limit(func);
         let result = runner.run(&strat, |calldata| {
+            // Once the deadline has passed, reject all remaining cases so that the runner
+            // aborts instead of executing them
+            if self.is_timed_out(start) {
+                return Err(TestCaseError::reject("timeout"))
+            }
+            if budget_exhausted() {
+                return Err(TestCaseError::reject("fuzz budget exhausted"))
+            }
+            if !shrinking.get() {
+                runs.set(runs.get() + 1);
+            }
+
             let mut evm = self.evm.borrow_mut();
 
             let (_, reason, _) = evm
-                .call_raw(Address::zero(), self.address, calldata, 0.into(), false)
+                .call_raw(self.sender, self.address, calldata, value, false)
                 .expect("could not make raw evm call");
 
             let success = evm.check_success(self.address, &reason, should_fail);
+            if !success {
+                shrinking.set(true);
+            }
+            // the logs of fuzz cases are not collected, so they are dropped as they come
+            evm.take_logs();
 
             // This will panic and get caught by the executor
             proptest::prop_assert!(success);
 
             Ok(())
         });
+        self.evm.borrow_mut().set_gas_limit(prev_gas_limit);
 
+        let timed_out = self.is_timed_out(start);
         let (success, counterexample) = match result {
-            Ok(_) => (true, None),
+            Ok(_) => (!timed_out, None),
+            Err(TestError::Abort(_)) if timed_out => (false, None),
+            // running out of budget without finding a counterexample is a pass
+            Err(TestError::Abort(_)) if budget_exhausted() => (true, None),
             Err(TestError::Fail(_, value)) => {
                 // skip the function selector when decoding
                 let args = func.decode_input(&value.as_ref()[4..])?;
@@ -181,9 +598,21 @@ impl<'a, S, E: Evm<S>> ContractRunner<'a, S, E> {
         };
 
         let duration = Instant::now().duration_since(start);
-        tracing::trace!(?duration, %success);
-
-        Ok(TestResult { success, gas_used: None, counterexample })
+        let runs = runs.get();
+        tracing::trace!(?duration, %success, %timed_out, %runs);
+
+        Ok(TestResult {
+            success,
+            gas_used: None,
+            counterexample,
+            timed_out,
+            logs: Vec::new(),
+            revert_reason: None,
+            storage_diff: Vec::new(),
+            runs: Some(runs),
+            warnings,
+            violated_invariants: Vec::new(),
+        })
     }
 }
 
@@ -191,45 +620,66 @@ impl<'a, S, E: Evm<S>> ContractRunner<'a, S, E> {
 mod tests {
     use super::*;
     use crate::test_helpers::COMPILED;
-    use evm::Config;
+
     use std::marker::PhantomData;
 
+    #[test]
+    fn storage_diff() {
+        let address = Address::repeat_byte(1);
+        let slot = |i| H256::from_low_u64_be(i);
+        // the values before the writes
+        let written =
+            vec![(address, vec![(slot(0), slot(1)), (slot(1), slot(1)), (slot(2), slot(0))])];
+        let written = written
+            .into_iter()
+            .map(|(address, slots)| (address, slots.into_iter().collect()))
+            .collect::<BTreeMap<_, _>>();
+        let current = [slot(2), slot(1), slot(3)];
+
+        let diff = StorageChange::diff(&written, |_, slot| current[slot.to_low_u64_be() as usize]);
+        assert_eq!(
+            diff,
+            vec![
+                StorageChange { address, slot: slot(0), old: slot(1), new: slot(2) },
+                StorageChange { address, slot: slot(2), old: H256::zero(), new: slot(3) },
+            ]
+        );
+    }
+
     mod sputnik {
         use dapp_utils::get_func;
-        use evm_adapters::sputnik::{
-            helpers::{new_backend, new_vicinity},
-            Executor,
-        };
+        use evm_adapters::{sputnik::helpers::new_executor, GasMetering};
         use proptest::test_runner::Config as FuzzConfig;
 
         use super::*;
 
         #[test]
         fn test_runner() {
-            let cfg = Config::istanbul();
             let compiled = COMPILED.get("GreeterTest").expect("could not find contract");
             let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
-            let vicinity = new_vicinity();
-            let backend = new_backend(&vicinity, Default::default());
-            let evm = Executor::new(12_000_000, &cfg, &backend);
+            let evm = new_executor();
             super::test_runner(evm, addr, compiled);
         }
 
         #[test]
         fn test_fuzz_shrinking() {
-            let cfg = Config::istanbul();
             let compiled = COMPILED.get("GreeterTest").expect("could not find contract");
             let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
-            let vicinity = new_vicinity();
-            let backend = new_backend(&vicinity, Default::default());
-
-            let mut evm = Executor::new(12_000_000, &cfg, &backend);
+            let mut evm = new_executor();
             evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
 
             let mut runner = ContractRunner {
                 evm: Rc::new(RefCell::new(&mut evm)),
                 contract: compiled,
                 address: addr,
+                sender: Address::zero(),
+                timeout: None,
+                fuzz_max_time: None,
+                annotations: HashMap::new(),
+                fail_fast: false,
+                errors: None,
+                invariants: &[],
+                violated_invariants: BTreeSet::new(),
                 state: PhantomData,
             };
 
@@ -238,6 +688,8 @@ mod tests {
             let func = get_func("function testFuzzShrinking(uint256 x, uint256 y) public").unwrap();
             let res = runner.run_fuzz_test(&func, true, &mut fuzzer).unwrap();
             assert!(!res.success);
+            // the shrinking iterations are not counted as runs
+            assert!(res.runs.unwrap() <= FuzzConfig::default().cases);
 
             // get the counterexample with shrinking enabled by default
             let counterexample = res.counterexample.unwrap();
@@ -261,6 +713,93 @@ mod tests {
             let product_without_shrinking = args[0].saturating_mul(args[1]);
             assert!(product_without_shrinking > product_with_shrinking.into());
         }
+
+        #[test]
+        fn test_timeout() {
+            let compiled = COMPILED.get("TimeoutTest").expect("could not find contract");
+            let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+            // without gas metering, nothing but the timeout stops the loops
+            let mut evm = new_executor().gas_metering(GasMetering::Disabled);
+            evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+
+            let timeout = Duration::from_millis(100);
+            let mut runner = ContractRunner::new(&mut evm, compiled, addr).timeout(Some(timeout));
+
+            let func = get_func("function testLoop() public").unwrap();
+            let start = Instant::now();
+            let res = runner.run_test(&func, false).unwrap();
+            assert!(start.elapsed() >= timeout);
+            assert!(res.timed_out);
+            assert!(!res.success);
+
+            let mut fuzzer = TestRunner::new(FuzzConfig::default());
+            let func = get_func("function testFuzzLoop(uint256 x) public").unwrap();
+            let res = runner.run_fuzz_test(&func, false, &mut fuzzer).unwrap();
+            assert!(res.timed_out);
+            assert!(!res.success);
+        }
+
+        #[test]
+        fn test_fuzz_max_time() {
+            let compiled = COMPILED.get("GreeterTest").expect("could not find contract");
+            let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+            let mut evm = new_executor();
+            evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+
+            let mut runner = ContractRunner::new(&mut evm, compiled, addr)
+                .fuzz_max_time(Some(std::time::Duration::from_millis(500)));
+
+            // a single case so that only the time budget lets it run more than once
+            let mut fuzzer = TestRunner::new(FuzzConfig { cases: 1, ..Default::default() });
+            let func = get_func("function testFuzzing(string myGreeting) public").unwrap();
+            let res = runner.run_fuzz_test(&func, true, &mut fuzzer).unwrap();
+            assert!(res.success);
+            assert!(!res.timed_out);
+            assert!(res.runs.unwrap() > 1);
+        }
+
+        #[test]
+        fn test_storage_diff() {
+            let compiled = COMPILED.get("GreeterTest").expect("could not find contract");
+            let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+            let mut evm = new_executor();
+            evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+            let mut runner = ContractRunner::new(&mut evm, compiled, addr);
+
+            // only the greeting gets written by the test, the greeter is stored by `setUp()`
+            let func = get_func("function testGreeting() public").unwrap();
+            let res = runner.run_test(&func, true).unwrap();
+            assert_eq!(res.storage_diff.len(), 1);
+            let change = &res.storage_diff[0];
+            assert_ne!(change.address, addr);
+            assert_eq!(change.old, H256::zero());
+            assert_ne!(change.new, H256::zero());
+        }
+
+        #[test]
+        fn test_logs() {
+            let compiled = COMPILED.get("LogTest").expect("could not find contract");
+            let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+            let mut evm = new_executor();
+            evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+            let mut runner = ContractRunner::new(&mut evm, compiled, addr);
+
+            let func = get_func("function testLogs() public").unwrap();
+            let res = runner.run_test(&func, true).unwrap();
+            assert!(res.success);
+            let names = res
+                .logs
+                .iter()
+                .map(|log| log.decoded.as_ref().unwrap().name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(names, vec!["log_named_uint", "Greeted", "log_named_uint"]);
+            let greeted = res.logs[1].decoded.as_ref().unwrap();
+            assert_eq!(greeted.params[1], ("greeting".to_owned(), "gm".to_owned()));
+
+            // logs of previous tests are not included
+            let res = runner.run_test(&func, true).unwrap();
+            assert_eq!(res.logs.len(), 3);
+        }
     }
 
     mod evmodin {
@@ -290,6 +829,14 @@ mod tests {
             evm: Rc::new(RefCell::new(&mut evm)),
             contract: compiled,
             address: addr,
+            sender: Address::zero(),
+            timeout: None,
+            fuzz_max_time: None,
+            annotations: HashMap::new(),
+            fail_fast: false,
+            errors: None,
+            invariants: &[],
+            violated_invariants: BTreeSet::new(),
             state: PhantomData,
         };
 
@@ -0,0 +1,153 @@
+//! Generates skeleton test contracts from the ABI of the contract under test
+
+use ethers::abi::{Abi, Function, Param, ParamType};
+use std::collections::HashMap;
+
+/// Returns the source of a `<name>Test` contract which deploys `name` in its `setUp()`, with
+/// an empty test for every function of the ABI and a fuzz test stub for the functions whose
+/// parameters are all value types. `path` is the import path of the contract under test and
+/// `pragma` the version requirement of the generated file, e.g. `^0.8.0`.
+pub fn generate_test(name: &str, path: &str, pragma: &str, abi: &Abi) -> String {
+    let target = variable_name(name);
+
+    let mut out = format!(
+        "// SPDX-License-Identifier: UNLICENSED\npragma solidity {};\n\nimport \"ds-test/test.sol\";\nimport \"{}\";\n\n",
+        pragma, path
+    );
+    out.push_str(&format!("contract {}Test is DSTest {{\n    {} {};\n\n", name, name, target));
+
+    let args = abi
+        .constructor()
+        .map(|constructor| constructor.inputs.iter().map(default_value).collect::<Vec<_>>())
+        .unwrap_or_default();
+    out.push_str(&format!(
+        "    function setUp() public {{\n        {} = new {}({});\n    }}\n",
+        target,
+        name,
+        args.join(", ")
+    ));
+
+    // overloaded functions get numbered so that the test names stay unique
+    let mut seen = HashMap::new();
+    for function in abi.functions() {
+        let count = seen.entry(function.name.as_str()).or_insert(0);
+        *count += 1;
+        let suffix = if *count > 1 { count.to_string() } else { String::new() };
+        let test_name = format!("{}{}", capitalize(&function.name), suffix);
+
+        out.push_str(&format!("\n    function test{}() public {{\n", test_name));
+        out.push_str(&format!("        // TODO: test {}.{}\n    }}\n", target, function.name));
+
+        if is_fuzzable(function) {
+            let params = param_names(function);
+            let declarations = function
+                .inputs
+                .iter()
+                .zip(&params)
+                .map(|(param, name)| format!("{} {}", param.kind, name))
+                .collect::<Vec<_>>();
+            out.push_str(&format!(
+                "\n    function testFuzz{}({}) public {{\n",
+                test_name,
+                declarations.join(", ")
+            ));
+            out.push_str(&format!(
+                "        // TODO: {}.{}({});\n    }}\n",
+                target,
+                function.name,
+                params.join(", ")
+            ));
+        }
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+/// Functions can only be fuzzed if they take inputs, all of which the fuzzer can generate
+fn is_fuzzable(function: &Function) -> bool {
+    let value_type = |param: &Param| {
+        matches!(
+            param.kind,
+            ParamType::Address |
+                ParamType::Bool |
+                ParamType::Int(_) |
+                ParamType::Uint(_) |
+                ParamType::FixedBytes(_)
+        )
+    };
+    !function.inputs.is_empty() && function.inputs.iter().all(value_type)
+}
+
+/// The names of the function's parameters, e.g. `arg1` for unnamed ones
+fn param_names(function: &Function) -> Vec<String> {
+    function
+        .inputs
+        .iter()
+        .enumerate()
+        .map(
+            |(i, param)| {
+                if param.name.is_empty() {
+                    format!("arg{}", i)
+                } else {
+                    param.name.clone()
+                }
+            },
+        )
+        .collect()
+}
+
+/// A placeholder value for a constructor argument, which compiles for any type
+fn default_value(param: &Param) -> String {
+    match &param.kind {
+        ParamType::Address => "address(0)".to_owned(),
+        ParamType::Bool => "false".to_owned(),
+        ParamType::Int(_) | ParamType::Uint(_) => "0".to_owned(),
+        ParamType::String | ParamType::Bytes => "\"\"".to_owned(),
+        ParamType::FixedBytes(size) => format!("bytes{}(0)", size),
+        ParamType::Array(_) => format!("new {}(0)", param.kind),
+        // fixed arrays and structs do not have a literal which works in all versions
+        _ => format!("/* TODO: {} {} */", param.kind, param.name),
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+fn variable_name(contract: &str) -> String {
+    let mut chars = contract.chars();
+    chars.next().map(|first| first.to_lowercase().chain(chars).collect()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_test_contract() {
+        let abi: Abi = serde_json::from_str(
+            r#"[
+                { "type": "constructor", "inputs": [{ "name": "owner", "type": "address" }], "stateMutability": "nonpayable" },
+                { "type": "function", "name": "transfer", "inputs": [{ "name": "to", "type": "address" }, { "name": "", "type": "uint256" }], "outputs": [], "stateMutability": "nonpayable" },
+                { "type": "function", "name": "transfer", "inputs": [{ "name": "to", "type": "address" }, { "name": "data", "type": "bytes" }], "outputs": [], "stateMutability": "nonpayable" },
+                { "type": "function", "name": "totalSupply", "inputs": [], "outputs": [{ "name": "", "type": "uint256" }], "stateMutability": "view" }
+            ]"#,
+        )
+        .unwrap();
+        let source = generate_test("Token", "src/Token.sol", "^0.8.0", &abi);
+
+        assert!(source.contains("import \"src/Token.sol\";"));
+        assert!(source.contains("contract TokenTest is DSTest {\n    Token token;"));
+        assert!(source.contains("token = new Token(address(0));"));
+        assert!(source.contains("function testTotalSupply() public {"));
+        assert!(!source.contains("testFuzzTotalSupply"));
+        assert!(source.contains("function testTransfer() public {"));
+        assert!(source.contains("function testFuzzTransfer(address to, uint256 arg1) public {"));
+        assert!(source.contains("// TODO: token.transfer(to, arg1);"));
+        // the overload taking bytes cannot be fuzzed
+        assert!(source.contains("function testTransfer2() public {"));
+        assert!(!source.contains("testFuzzTransfer2"));
+    }
+}
@@ -3,21 +3,20 @@ use evm_adapters::sputnik::{vicinity, ForkMemoryBackend};
 use regex::Regex;
 use structopt::StructOpt;
 
-use dapp::MultiContractRunnerBuilder;
+use dapp::{AddressResolver, ArtifactStorage, FsStorage, MultiContractRunnerBuilder, TestCache};
 use dapp_solc::SolcBuilder;
 
-use ansi_term::Colour;
-
 mod dapp_opts;
-use dapp_opts::{BuildOpts, EvmType, Opts, Subcommands};
+use dapp_opts::{BuildOpts, EvmType, GenerateKind, InspectField, Opts, ReporterType, Subcommands};
 
-use std::convert::TryFrom;
+use std::{convert::TryFrom, path::Path};
 
 mod utils;
 
 #[tracing::instrument(err)]
 fn main() -> eyre::Result<()> {
     utils::subscriber();
+    dapp_utils::load_dotenv(".env")?;
 
     let opts = Opts::from_args();
     match opts.sub {
@@ -26,55 +25,116 @@ fn main() -> eyre::Result<()> {
                 BuildOpts { contracts, remappings, remappings_env, lib_paths, out_path, evm_version },
             env,
             json,
+            reporter,
             pattern,
+            match_contract,
+            no_match_contract,
+            match_path,
+            require_setup,
             evm_type,
             no_compile,
+            list,
+            failed,
+            fail_fast,
+            timeout,
+            fuzz_max_time,
+            gas_metering,
+            address_scheme,
+            addresses,
             fork_url,
             fork_block_number,
         } => {
+            // `--json` is kept as a shorthand for `--reporter json`
+            let reporter = if json { ReporterType::Json } else { reporter };
+
             // get the remappings / paths
             let remappings = utils::merge(remappings, remappings_env);
             let lib_paths = utils::default_path(lib_paths)?;
 
+            let cache_path = utils::test_cache_path(&out_path);
+
             // prepare the builder
-            let builder = MultiContractRunnerBuilder::default()
+            let mut builder = MultiContractRunnerBuilder::default()
                 .contracts(&contracts)
                 .remappings(&remappings)
                 .libraries(&lib_paths)
                 .out_path(out_path)
                 .fuzzer(proptest::test_runner::TestRunner::default())
-                .skip_compilation(no_compile);
+                .skip_compilation(no_compile)
+                .fail_fast(fail_fast)
+                .evm_version(evm_version)
+                .block_env(env.block_env());
+            builder.contract_pattern = match_contract;
+            builder.no_contract_pattern = no_match_contract;
+            builder.path_pattern = match_path;
+            builder.require_setup = require_setup;
+            builder.timeout = timeout;
+            builder.fuzz_max_time = fuzz_max_time;
+            builder.gas_metering = Some(gas_metering);
+            builder.sender = env.sender;
+            builder.sender_balance = env.sender_balance;
+            builder.initial_balance = env.initial_balance;
+            let overrides = match addresses {
+                Some(path) => AddressResolver::load_overrides(path)?,
+                None => Default::default(),
+            };
+            builder.address_resolver = AddressResolver::new(address_scheme).overrides(overrides);
+            if failed {
+                let cache = TestCache::load(&cache_path)?;
+                if cache.is_empty() {
+                    println!("No failed tests to re-run");
+                    return Ok(())
+                }
+                builder = builder.only_tests(cache.failures);
+            }
 
             // run the tests depending on the chosen EVM
             match evm_type {
                 #[cfg(feature = "sputnik-evm")]
                 EvmType::Sputnik => {
-                    use evm_adapters::sputnik::Executor;
-                    use sputnik::backend::MemoryBackend;
-                    let cfg = evm_version.sputnik_cfg();
+                    use evm_adapters::{sputnik::Executor, Evm};
+                    use sputnik::backend::{MemoryBackend, MemoryVicinity};
+                    let cfg = evm_version.sputnik_cfg()?;
+                    if env.base_fee != 0 {
+                        eyre::bail!("--base-fee needs London, which Sputnik does not support yet")
+                    }
 
                     if let Some(url) = fork_url {
+                        // the tests run in the forked block
+                        builder.block_env = None;
                         let provider = Provider::try_from(url.as_str())?;
                         let vicinity = {
                             let rt =
                                 tokio::runtime::Runtime::new().expect("could not start tokio rt");
                             rt.block_on(vicinity(&provider, fork_block_number))?
                         };
+                        let vicinity = match env.chain_id {
+                            Some(chain_id) => {
+                                MemoryVicinity { chain_id: chain_id.into(), ..vicinity }
+                            }
+                            None => vicinity,
+                        };
+                        // the test calls pay the configured price, not the forked block's one
+                        let tx_env = env.tx_env();
+                        let gas_price = tx_env.effective_gas_price(env.base_fee.into())?;
+                        let vicinity = MemoryVicinity { gas_price, ..vicinity };
                         let backend = MemoryBackend::new(&vicinity, Default::default());
                         let backend = ForkMemoryBackend::new(provider, backend);
-                        let evm = Executor::new(env.gas_limit, &cfg, &backend);
+                        let mut evm = Executor::new(env.gas_limit, &cfg, &backend);
+                        evm.set_tx_env(tx_env)?;
 
-                        test(builder, evm, pattern, json)?;
+                        test(builder, evm, pattern, reporter, list, &cache_path)?;
                     } else {
-                        let vicinity = env.sputnik_state();
+                        let vicinity = env.sputnik_state()?;
                         let backend = MemoryBackend::new(&vicinity, Default::default());
-                        let evm = Executor::new(env.gas_limit, &cfg, &backend);
-                        test(builder, evm, pattern, json)?;
+                        let mut evm = Executor::new(env.gas_limit, &cfg, &backend);
+                        evm.set_tx_env(env.tx_env())?;
+                        test(builder, evm, pattern, reporter, list, &cache_path)?;
                     }
                 }
                 #[cfg(feature = "evmodin-evm")]
                 EvmType::EvmOdin => {
-                    use evm_adapters::evmodin::EvmOdin;
+                    use evm_adapters::{evmodin::EvmOdin, Evm};
                     use evmodin::tracing::NoopTracer;
 
                     let revision = evm_version.evmodin_cfg();
@@ -83,72 +143,215 @@ fn main() -> eyre::Result<()> {
                     // provided generically when we add the Forking host(s).
                     let host = env.evmodin_state();
 
-                    let evm = EvmOdin::new(host, env.gas_limit, revision, NoopTracer);
-                    test(builder, evm, pattern, json)?;
+                    let mut evm = EvmOdin::new(host, env.gas_limit, revision, NoopTracer);
+                    evm.set_tx_env(env.tx_env())?;
+                    test(builder, evm, pattern, reporter, list, &cache_path)?;
                 }
             }
         }
         Subcommands::Build {
             opts:
-                BuildOpts { contracts, remappings, remappings_env, lib_paths, out_path, evm_version: _ },
+                BuildOpts { contracts, remappings, remappings_env, lib_paths, out_path, evm_version },
         } => {
             // build the contracts
             let remappings = utils::merge(remappings, remappings_env);
             let lib_paths = utils::default_path(lib_paths)?;
             // TODO: Do we also want to include the file path in the contract map so
             // that we're more compatible with dapptools' artifact?
-            let contracts = SolcBuilder::new(&contracts, &remappings, &lib_paths)?.build_all()?;
+            let contracts = SolcBuilder::new(&contracts, &remappings, &lib_paths)?
+                .evm_version(evm_version.solc_evm_version())
+                .build_all()?;
 
-            let out_file = utils::open_file(out_path)?;
+            FsStorage::new(out_path).store(&contracts)?;
+        }
+        Subcommands::CallGraph {
+            opts: BuildOpts { contracts, remappings, remappings_env, lib_paths, .. },
+            json,
+        } => {
+            let remappings = utils::merge(remappings, remappings_env);
+            let lib_paths = utils::default_path(lib_paths)?;
+            let asts = SolcBuilder::new(&contracts, &remappings, &lib_paths)?.build_asts()?;
+
+            let graph = dapp_solc::ast::CallGraph::new(&asts);
+            if json {
+                println!("{}", serde_json::to_string(&graph)?);
+            } else {
+                println!("{}", graph.to_dot());
+            }
+        }
+        Subcommands::Ast {
+            file,
+            query,
+            opts: BuildOpts { remappings, remappings_env, lib_paths, .. },
+        } => {
+            let remappings = utils::merge(remappings, remappings_env);
+            let lib_paths = utils::default_path(lib_paths)?;
+            // only the provided file (and its imports) gets compiled
+            let mut asts = SolcBuilder::new(&file, &remappings, &lib_paths)?.build_asts()?;
+            let ast = asts
+                .remove(&dapp_solc::canonicalize(&file)?)
+                .ok_or_else(|| eyre::eyre!("no AST for {}", file))?;
 
-            // dump as json
-            serde_json::to_writer(out_file, &contracts)?;
+            match query {
+                Some(selector) => {
+                    let nodes = dapp_solc::ast::query(&ast, &selector)?;
+                    println!("{}", serde_json::to_string(&nodes)?);
+                }
+                None => println!("{}", serde_json::to_string(&ast)?),
+            }
+        }
+        Subcommands::BumpPragma {
+            version,
+            opts: BuildOpts { contracts, remappings, remappings_env, lib_paths, .. },
+        } => {
+            let remappings = utils::merge(remappings, remappings_env);
+            let lib_paths = utils::default_path(lib_paths)?;
+            bump_pragma(&contracts, &remappings, &lib_paths, &version)?;
+        }
+        Subcommands::Inspect {
+            contract,
+            field: InspectField::Storage,
+            opts: BuildOpts { contracts, remappings, remappings_env, lib_paths, .. },
+            json,
+        } => {
+            let remappings = utils::merge(remappings, remappings_env);
+            let lib_paths = utils::default_path(lib_paths)?;
+            let mut layouts =
+                SolcBuilder::new(&contracts, &remappings, &lib_paths)?.build_storage_layouts()?;
+            let layout = layouts
+                .remove(&contract)
+                .ok_or_else(|| eyre::eyre!("no contract named {}", contract))?;
+
+            if json {
+                println!("{}", serde_json::to_string(&layout)?);
+            } else {
+                for slot in &layout.storage {
+                    println!(
+                        "{} (slot: {}, offset: {}): {}",
+                        slot.label,
+                        slot.slot,
+                        slot.offset,
+                        layout.type_label(slot)
+                    );
+                }
+            }
+        }
+        Subcommands::Generate {
+            kind:
+                GenerateKind::Test {
+                    contract,
+                    pragma,
+                    opts: BuildOpts { contracts, remappings, remappings_env, lib_paths, .. },
+                },
+        } => {
+            let remappings = utils::merge(remappings, remappings_env);
+            let lib_paths = utils::default_path(lib_paths)?;
+            let mut builder = SolcBuilder::new(&contracts, &remappings, &lib_paths)?;
+            let compiled = builder.build_all()?;
+            let abi = &compiled
+                .get(&contract)
+                .ok_or_else(|| eyre::eyre!("no contract named {}", contract))?
+                .abi;
+            let path = builder
+                .sources()
+                .get(&contract)
+                .ok_or_else(|| eyre::eyre!("could not find the source of {}", contract))?;
+
+            let pragma = match pragma {
+                Some(pragma) => pragma,
+                None => pragma_solidity(&std::fs::read_to_string(path)?)
+                    .ok_or_else(|| eyre::eyre!("{} has no `pragma solidity`", path))?,
+            };
+            print!("{}", dapp::generate_test(&contract, path, &pragma, abi));
         }
     }
 
     Ok(())
 }
 
+/// Rewrites the `pragma solidity` statements of the contracts matching the glob and recompiles
+/// them with the new version
+fn bump_pragma(
+    contracts: &str,
+    remappings: &[String],
+    lib_paths: &[String],
+    version: &str,
+) -> eyre::Result<()> {
+    let asts = SolcBuilder::new(contracts, remappings, lib_paths)?.build_asts()?;
+
+    // the ASTs include the imported files, which we don't touch
+    let mut updated = 0;
+    for file in glob::glob(contracts)? {
+        let path = dapp_solc::canonicalize(file?)?;
+        let ast = match asts.get(&path) {
+            Some(ast) => ast,
+            None => continue,
+        };
+        let patches = dapp_solc::patch::pragma_solidity(ast, version);
+        if patches.is_empty() {
+            continue
+        }
+        let source = std::fs::read_to_string(&path)?;
+        std::fs::write(&path, dapp_solc::patch::apply(&source, patches)?)?;
+        updated += 1;
+    }
+    println!("Updated the pragma of {} files to {}", updated, version);
+
+    if let Err(err) = SolcBuilder::new(contracts, remappings, lib_paths)?.build_all() {
+        eprintln!("The contracts do not compile with the new version:\n{}", err);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Returns the version requirement of the first `pragma solidity` statement of the source
+fn pragma_solidity(source: &str) -> Option<String> {
+    source.lines().find_map(|line| {
+        let requirement = line.trim().strip_prefix("pragma solidity")?;
+        Some(requirement.split(';').next()?.trim().to_owned())
+    })
+}
+
 fn test<S, E: evm_adapters::Evm<S>>(
     builder: MultiContractRunnerBuilder,
     evm: E,
     pattern: Regex,
-    json: bool,
+    reporter: ReporterType,
+    list: bool,
+    cache_path: &Path,
 ) -> eyre::Result<()> {
+    let mut reporter_impl = reporter.reporter();
+    reporter_impl.on_compile_start();
     let mut runner = builder.build(evm)?;
+    reporter_impl.on_compile_end();
 
-    let results = runner.test(pattern)?;
-
-    if json {
-        let res = serde_json::to_string(&results)?;
-        println!("{}", res);
-    } else {
-        // Dapptools-style printing
-        for (i, (contract_name, tests)) in results.iter().enumerate() {
-            if i > 0 {
-                println!()
-            }
-            if !tests.is_empty() {
-                println!("Running {} tests for {}", tests.len(), contract_name);
-            }
-
-            for (name, result) in tests {
-                let status = if result.success {
-                    Colour::Green.paint("[PASS]")
-                } else {
-                    Colour::Red.paint("[FAIL]")
-                };
-                println!(
-                    "{} {} (gas: {})",
-                    status,
-                    name,
-                    result
-                        .gas_used
-                        .map(|x| x.to_string())
-                        .unwrap_or_else(|| "[fuzztest]".to_string())
-                );
+    if list {
+        let tests = runner.list(&pattern);
+        if matches!(reporter, ReporterType::Json) {
+            println!("{}", serde_json::to_string(&tests)?);
+        } else {
+            for (contract_name, tests) in tests {
+                println!("{}", contract_name);
+                for test in tests {
+                    println!("  {} [{:?}]", test.signature, test.kind);
+                }
             }
         }
+        return Ok(())
+    }
+
+    let results = runner.test_with_reporter(pattern.clone(), reporter_impl.as_mut())?;
+
+    // persist the failures so that they can be re-run with `--failed`
+    TestCache::from_results(&results).save(cache_path)?;
+
+    let summary = runner.summary(&pattern, &results)?;
+    reporter_impl.on_summary(&summary);
+    if summary.skipped > 0 {
+        eprintln!("Skipped {} tests after the first failure", summary.skipped);
+    }
+    if summary.exit_code() != 0 {
+        std::process::exit(summary.exit_code());
     }
 
     Ok(())
@@ -1,7 +1,8 @@
 use structopt::StructOpt;
 
-use ethers::types::Address;
-use std::{path::PathBuf, str::FromStr};
+use ethers::types::{Address, U256};
+use evm_adapters::{EvmVersion, GasMetering};
+use std::{path::PathBuf, str::FromStr, time::Duration};
 
 #[derive(Debug, StructOpt)]
 pub struct Opts {
@@ -17,6 +18,14 @@ pub enum Subcommands {
         #[structopt(help = "print the test results in json format", long, short)]
         json: bool,
 
+        #[structopt(
+            help = "how to print the test results (pretty, quiet, json, junit, ndjson)",
+            long,
+            alias = "output",
+            default_value = "pretty"
+        )]
+        reporter: ReporterType,
+
         #[structopt(flatten)]
         env: Env,
 
@@ -28,6 +37,18 @@ pub enum Subcommands {
         )]
         pattern: regex::Regex,
 
+        #[structopt(long, help = "only run tests in contracts matching regex")]
+        match_contract: Option<regex::Regex>,
+
+        #[structopt(long, help = "only run tests in contracts not matching regex")]
+        no_match_contract: Option<regex::Regex>,
+
+        #[structopt(long, help = "only run tests in source files whose path matches regex")]
+        match_path: Option<regex::Regex>,
+
+        #[structopt(long, help = "fail if test contracts matching regex do not have a setUp()")]
+        require_setup: Option<regex::Regex>,
+
         #[structopt(flatten)]
         opts: BuildOpts,
 
@@ -42,12 +63,55 @@ pub enum Subcommands {
         #[structopt(help = "skip re-compilation", long, short)]
         no_compile: bool,
 
+        #[structopt(help = "list the matching tests without running them", long)]
+        list: bool,
+
+        #[structopt(help = "only re-run the tests which failed in the last run", long)]
+        failed: bool,
+
+        #[structopt(help = "stop running tests after the first failure", long)]
+        fail_fast: bool,
+
+        #[structopt(
+            help = "interrupt and fail tests which run for longer than this (e.g. 500ms, 10s)",
+            long,
+            parse(try_from_str = parse_duration)
+        )]
+        timeout: Option<Duration>,
+
+        #[structopt(
+            help = "how to meter the gas of the tests (ignore-refunds, refunded or disabled)",
+            long,
+            default_value = "ignore-refunds"
+        )]
+        gas_metering: GasMetering,
+
+        #[structopt(
+            help = "fuzz each test for this long (e.g. 30s, 2m) instead of a fixed number of runs",
+            long,
+            parse(try_from_str = parse_duration)
+        )]
+        fuzz_max_time: Option<Duration>,
+
+        #[structopt(
+            help = "how to pick the addresses of the deployed contracts (create, sequential or sequential:<start address>)",
+            long,
+            default_value = "create"
+        )]
+        address_scheme: dapp::AddressScheme,
+
+        #[structopt(
+            help = "JSON file mapping contract names to the addresses to deploy them at",
+            long
+        )]
+        addresses: Option<PathBuf>,
+
         #[structopt(
             help = "fetch state over a remote instead of starting from empty state",
             long,
             short
         )]
-        #[structopt(alias = "rpc-url")]
+        #[structopt(alias = "rpc-url", env = "DAPP_FORK_URL")]
         fork_url: Option<String>,
 
         #[structopt(help = "pins the block number for the state fork", long)]
@@ -58,6 +122,90 @@ pub enum Subcommands {
         #[structopt(flatten)]
         opts: BuildOpts,
     },
+    #[structopt(about = "print the static call graph of your smart contracts")]
+    CallGraph {
+        #[structopt(flatten)]
+        opts: BuildOpts,
+
+        #[structopt(help = "print the call graph in json instead of the DOT format", long, short)]
+        json: bool,
+    },
+    #[structopt(about = "print the AST of a source file, or the nodes matching a query")]
+    Ast {
+        #[structopt(help = "the source file")]
+        file: String,
+
+        #[structopt(
+            help = "only print the nodes matching this selector, e.g. `ContractDefinition[name=Foo] FunctionDefinition`",
+            long,
+            short
+        )]
+        query: Option<String>,
+
+        #[structopt(flatten)]
+        opts: BuildOpts,
+    },
+    #[structopt(about = "rewrite the `pragma solidity` statements of your smart contracts")]
+    BumpPragma {
+        #[structopt(help = "the new version requirement, e.g. ^0.8.0")]
+        version: String,
+
+        #[structopt(flatten)]
+        opts: BuildOpts,
+    },
+    #[structopt(about = "inspect the compiler output of a contract, e.g. its storage layout")]
+    Inspect {
+        #[structopt(help = "the name of the contract")]
+        contract: String,
+
+        #[structopt(help = "what to inspect (storage)")]
+        field: InspectField,
+
+        #[structopt(flatten)]
+        opts: BuildOpts,
+
+        #[structopt(help = "print the output in json format", long, short)]
+        json: bool,
+    },
+    #[structopt(about = "generate boilerplate for your smart contracts")]
+    Generate {
+        #[structopt(subcommand)]
+        kind: GenerateKind,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum GenerateKind {
+    #[structopt(about = "print a skeleton test contract for a contract, based on its ABI")]
+    Test {
+        #[structopt(help = "the name of the contract to test")]
+        contract: String,
+
+        #[structopt(
+            help = "the version requirement of the generated file, defaults to the one of the tested contract",
+            long
+        )]
+        pragma: Option<String>,
+
+        #[structopt(flatten)]
+        opts: BuildOpts,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum InspectField {
+    Storage,
+}
+
+impl FromStr for InspectField {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "storage" | "storage-layout" => InspectField::Storage,
+            other => eyre::bail!("cannot inspect {}", other),
+        })
+    }
 }
 
 #[derive(Debug, StructOpt)]
@@ -86,7 +234,12 @@ pub struct BuildOpts {
     )]
     pub out_path: PathBuf,
 
-    #[structopt(help = "choose the evm version", long, default_value = "berlin")]
+    #[structopt(
+        help = "the hard fork which the compiler targets and the EVM follows",
+        long,
+        alias = "hardfork",
+        default_value = "berlin"
+    )]
     pub evm_version: EvmVersion,
 }
 
@@ -113,56 +266,53 @@ impl FromStr for EvmType {
 }
 
 #[derive(Clone, Debug)]
-pub enum EvmVersion {
-    Frontier,
-    Istanbul,
-    Berlin,
-    London,
+pub enum ReporterType {
+    Pretty,
+    Quiet,
+    Json,
+    JUnit,
+    Ndjson,
 }
 
-#[cfg(feature = "sputnik-evm")]
-use sputnik::Config;
-
-#[cfg(feature = "evmodin-evm")]
-use evmodin::Revision;
+impl FromStr for ReporterType {
+    type Err = eyre::Error;
 
-impl EvmVersion {
-    #[cfg(feature = "sputnik-evm")]
-    pub fn sputnik_cfg(self) -> Config {
-        use EvmVersion::*;
-        match self {
-            Frontier => Config::frontier(),
-            Istanbul => Config::istanbul(),
-            Berlin => Config::berlin(),
-            other => panic!("The {:?} hard fork is unsupported on Sputnik", other),
-        }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "pretty" => ReporterType::Pretty,
+            "quiet" => ReporterType::Quiet,
+            "json" => ReporterType::Json,
+            "junit" => ReporterType::JUnit,
+            "ndjson" => ReporterType::Ndjson,
+            other => eyre::bail!("unknown reporter {}", other),
+        })
     }
+}
 
-    #[cfg(feature = "evmodin-evm")]
-    pub fn evmodin_cfg(self) -> Revision {
-        use EvmVersion::*;
+impl ReporterType {
+    pub fn reporter(&self) -> Box<dyn dapp::Reporter> {
         match self {
-            Frontier => Revision::Frontier,
-            Istanbul => Revision::Istanbul,
-            Berlin => Revision::Berlin,
-            London => Revision::London,
+            ReporterType::Pretty => Box::new(dapp::PrettyReporter),
+            ReporterType::Quiet => Box::new(dapp::QuietReporter),
+            ReporterType::Json => Box::new(dapp::JsonReporter),
+            ReporterType::JUnit => Box::new(dapp::JUnitReporter),
+            ReporterType::Ndjson => Box::new(dapp::NdjsonReporter),
         }
     }
 }
 
-impl FromStr for EvmVersion {
-    type Err = eyre::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use EvmVersion::*;
-        Ok(match s.to_lowercase().as_str() {
-            "frontier" => Frontier,
-            "istanbul" => Istanbul,
-            "berlin" => Berlin,
-            "london" => London,
-            _ => eyre::bail!("unsupported evm version: {}", s),
-        })
-    }
+/// Parses durations such as `500ms`, `30s`, `2m` or `1h`. Plain numbers are seconds.
+fn parse_duration(s: &str) -> eyre::Result<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or_else(|| s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value.parse()?;
+    Ok(match unit {
+        "ms" => Duration::from_millis(value),
+        "" | "s" => Duration::from_secs(value),
+        "m" => Duration::from_secs(value * 60),
+        "h" => Duration::from_secs(value * 60 * 60),
+        other => eyre::bail!("unknown duration unit `{}`", other),
+    })
 }
 
 #[derive(Debug, StructOpt)]
@@ -170,8 +320,12 @@ pub struct Env {
     #[structopt(help = "the block gas limit", long, default_value = "25000000")]
     pub gas_limit: u64,
 
-    #[structopt(help = "the chainid opcode value", long, default_value = "1")]
-    pub chain_id: u64,
+    #[structopt(
+        help = "the chainid opcode value, which defaults to 1 or to the chain id of the fork",
+        long,
+        env = "DAPP_TEST_CHAINID"
+    )]
+    pub chain_id: Option<u64>,
 
     #[structopt(help = "the tx.gasprice value during EVM execution", long, default_value = "0")]
     pub gas_price: u64,
@@ -186,6 +340,7 @@ pub struct Env {
     #[structopt(
         help = "the block.coinbase value during EVM execution",
         long,
+        env = "DAPP_TEST_COINBASE",
         // TODO: It'd be nice if we could use Address::zero() here.
         default_value = "0x0000000000000000000000000000000000000000"
     )]
@@ -193,61 +348,121 @@ pub struct Env {
     #[structopt(
         help = "the block.timestamp value during EVM execution",
         long,
-        default_value = "0"
+        env = "DAPP_TEST_TIMESTAMP",
+        default_value = "1"
     )]
     pub block_timestamp: u64,
 
-    #[structopt(help = "the block.number value during EVM execution", long, default_value = "0")]
+    #[structopt(
+        help = "the block.number value during EVM execution",
+        long,
+        env = "DAPP_TEST_NUMBER",
+        default_value = "1"
+    )]
     pub block_number: u64,
 
     #[structopt(
         help = "the block.difficulty value during EVM execution",
         long,
+        env = "DAPP_TEST_DIFFICULTY",
         default_value = "0"
     )]
     pub block_difficulty: u64,
 
-    #[structopt(help = "the block.gaslimit value during EVM execution", long)]
+    #[structopt(
+        help = "the block.gaslimit value during EVM execution",
+        long,
+        env = "DAPP_TEST_GAS_LIMIT"
+    )]
     pub block_gas_limit: Option<u64>,
-    // TODO: Add configuration option for base fee.
+
+    #[structopt(
+        help = "the msg.sender value of the test calls",
+        long,
+        default_value = "0x0000000000000000000000000000000000000000"
+    )]
+    pub sender: Address,
+
+    #[structopt(help = "the wei balance of the sender", long, parse(try_from_str = U256::from_dec_str))]
+    pub sender_balance: Option<U256>,
+
+    #[structopt(help = "the wei balance of the test contracts", long, parse(try_from_str = U256::from_dec_str))]
+    pub initial_balance: Option<U256>,
+
+    #[structopt(
+        help = "the block.basefee value during EVM execution",
+        long,
+        env = "DAPP_TEST_BASEFEE",
+        default_value = "0"
+    )]
+    pub base_fee: u64,
+
+    #[structopt(help = "the EIP-1559 max fee per gas of the test calls", long)]
+    pub max_fee_per_gas: Option<u64>,
+
+    #[structopt(help = "the EIP-1559 max priority fee per gas of the test calls", long)]
+    pub max_priority_fee_per_gas: Option<u64>,
 }
 
 #[cfg(feature = "sputnik-evm")]
 use sputnik::backend::MemoryVicinity;
 
+#[cfg(feature = "evmodin-evm")]
+use evm_adapters::evmodin::HostExt;
 #[cfg(feature = "evmodin-evm")]
 use evmodin::util::mocked_host::MockedHost;
 
 impl Env {
+    /// The fee parameters of the test calls
+    pub fn tx_env(&self) -> evm_adapters::TxEnv {
+        evm_adapters::TxEnv {
+            gas_price: self.gas_price.into(),
+            max_fee_per_gas: self.max_fee_per_gas.map(Into::into),
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas.map(Into::into),
+        }
+    }
+
+    /// The block the tests run in
+    pub fn block_env(&self) -> evm_adapters::BlockEnv {
+        evm_adapters::BlockEnv {
+            number: self.block_number,
+            timestamp: self.block_timestamp,
+            coinbase: self.block_coinbase,
+            difficulty: self.block_difficulty.into(),
+            gas_limit: self.block_gas_limit.unwrap_or(self.gas_limit),
+            base_fee: self.base_fee.into(),
+        }
+    }
+
     #[cfg(feature = "sputnik-evm")]
-    pub fn sputnik_state(&self) -> MemoryVicinity {
-        MemoryVicinity {
-            chain_id: self.chain_id.into(),
+    pub fn sputnik_state(&self) -> eyre::Result<MemoryVicinity> {
+        let mut vicinity = MemoryVicinity {
+            chain_id: self.chain_id.unwrap_or(1).into(),
 
-            gas_price: self.gas_price.into(),
+            // sputnik reads `tx.gasprice` from the backend, so it has to be the price which
+            // the test calls actually pay
+            gas_price: self.tx_env().effective_gas_price(self.base_fee.into())?,
             origin: self.tx_origin,
 
-            block_coinbase: self.block_coinbase,
-            block_number: self.block_number.into(),
-            block_timestamp: self.block_timestamp.into(),
-            block_difficulty: self.block_difficulty.into(),
-            block_gas_limit: self.block_gas_limit.unwrap_or(self.gas_limit).into(),
+            block_coinbase: Default::default(),
+            block_number: Default::default(),
+            block_timestamp: Default::default(),
+            block_difficulty: Default::default(),
+            block_gas_limit: Default::default(),
             block_hashes: Vec::new(),
-        }
+        };
+        self.block_env().fill_vicinity(&mut vicinity);
+        Ok(vicinity)
     }
 
     #[cfg(feature = "evmodin-evm")]
     pub fn evmodin_state(&self) -> MockedHost {
         let mut host = MockedHost::default();
 
-        host.tx_context.chain_id = self.chain_id.into();
+        host.tx_context.chain_id = self.chain_id.unwrap_or(1).into();
         host.tx_context.tx_gas_price = self.gas_price.into();
         host.tx_context.tx_origin = self.tx_origin;
-        host.tx_context.block_coinbase = self.block_coinbase;
-        host.tx_context.block_number = self.block_number;
-        host.tx_context.block_timestamp = self.block_timestamp;
-        host.tx_context.block_difficulty = self.block_difficulty.into();
-        host.tx_context.block_gas_limit = self.block_gas_limit.unwrap_or(self.gas_limit);
+        host.set_block_env(self.block_env());
 
         host
     }
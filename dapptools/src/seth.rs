@@ -14,6 +14,8 @@ use structopt::StructOpt;
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
+    dapp_utils::load_dotenv(".env")?;
+
     let opts = Opts::from_args();
     match opts.sub {
         Subcommands::FromUtf8 { text } => {
@@ -111,6 +113,12 @@ async fn main() -> eyre::Result<()> {
             let provider = Provider::try_from(rpc_url)?;
             println!("{}", Seth::new(provider).gas_price().await?);
         }
+        Subcommands::StorageExplore { address, slots, block, rpc_url } => {
+            let provider = Provider::try_from(rpc_url)?;
+            for (slot, value) in Seth::new(provider).storage_explore(address, slots, block).await? {
+                println!("{:?} {:?}", slot, value);
+            }
+        }
         Subcommands::Keccak { data } => {
             println!("{}", SimpleSeth::keccak(&data)?);
         }
@@ -128,6 +128,18 @@ pub enum Subcommands {
         #[structopt(short, long, env = "ETH_RPC_URL")]
         rpc_url: String,
     },
+    #[structopt(name = "storage-explore")]
+    #[structopt(about = "Lists the populated storage slots of <address>")]
+    StorageExplore {
+        #[structopt(help = "the contract you want to inspect", parse(try_from_str = parse_name_or_address))]
+        address: NameOrAddress,
+        #[structopt(long, help = "how many sequential slots to scan", default_value = "64")]
+        slots: u64,
+        #[structopt(long, short, help = "the block you want to query, can also be earliest/latest/pending", parse(try_from_str = parse_block_id))]
+        block: Option<BlockId>,
+        #[structopt(short, long, env = "ETH_RPC_URL")]
+        rpc_url: String,
+    },
     #[structopt(name = "keccak")]
     #[structopt(about = "Keccak-256 hashes arbitrary data")]
     Keccak { data: String },
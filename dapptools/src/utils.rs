@@ -1,10 +1,4 @@
-use std::{
-    fs::{File, OpenOptions},
-    path::PathBuf,
-};
-
-/// Default deps path
-const DEFAULT_OUT_FILE: &str = "dapp.sol.json";
+use std::path::{Path, PathBuf};
 
 /// Initializes a tracing Subscriber for logging
 pub fn subscriber() {
@@ -36,35 +30,7 @@ pub fn merge(mut remappings: Vec<String>, remappings_env: Option<String>) -> Vec
     remappings
 }
 
-/// Opens the file at `out_path` for R/W and creates it if it doesn't exist.
-pub fn open_file(out_path: PathBuf) -> eyre::Result<File> {
-    Ok(if out_path.is_file() {
-        // get the file if it exists
-        OpenOptions::new().write(true).open(out_path)?
-    } else if out_path.is_dir() {
-        // get the directory if it exists & the default file path
-        let out_path = out_path.join(DEFAULT_OUT_FILE);
-
-        // get a file handler (overwrite any contents of the existing file)
-        OpenOptions::new().write(true).create(true).open(out_path)?
-    } else {
-        // otherwise try to create the entire path
-
-        // in case it's a directory, we must mkdir it
-        let out_path =
-            if out_path.to_str().ok_or_else(|| eyre::eyre!("not utf-8 path"))?.ends_with('/') {
-                std::fs::create_dir_all(&out_path)?;
-                out_path.join(DEFAULT_OUT_FILE)
-            } else {
-                // if it's a file path, we must mkdir the parent
-                let parent = out_path
-                    .parent()
-                    .ok_or_else(|| eyre::eyre!("could not get parent of {:?}", out_path))?;
-                std::fs::create_dir_all(parent)?;
-                out_path
-            };
-
-        // finally we get the handler
-        OpenOptions::new().write(true).create_new(true).open(out_path)?
-    })
+/// Returns the path of the test cache, which is stored next to the build artifacts
+pub fn test_cache_path(out_path: &Path) -> PathBuf {
+    dapp::FsStorage::new(out_path).path().with_file_name(dapp::TEST_CACHE_FILE)
 }
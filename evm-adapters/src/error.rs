@@ -0,0 +1,23 @@
+use ethers::{
+    contract::AbiError,
+    types::{Address, U256},
+};
+
+/// Errors which can occur while executing calls against an [`Evm`](crate::Evm)
+#[derive(thiserror::Error, Debug)]
+pub enum EvmError {
+    /// The calldata could not be encoded or the returned data could not be decoded
+    #[error(transparent)]
+    Abi(#[from] AbiError),
+    /// The call was made to an address without any code
+    #[error("there should be a smart contract at the destination address {0:?}")]
+    MissingContract(Address),
+    /// The transaction could not be included in a block with this base fee
+    #[error("the fee per gas {fee} is below the base fee {base_fee}")]
+    FeeBelowBaseFee { fee: U256, base_fee: U256 },
+    /// The EVM does not support the requested configuration
+    #[error("{0} is not supported by this EVM")]
+    Unsupported(String),
+}
+
+pub(crate) type Result<T, E = EvmError> = std::result::Result<T, E>;
@@ -1,10 +1,25 @@
-use crate::Evm;
+use crate::{error::Result, BlockEnv, Evm, EvmError, EvmVersion, TxEnv};
 
-use ethers::types::{Address, Bytes, U256};
+use ethers::{
+    abi::RawLog,
+    types::{Address, Bytes, H256, U256},
+};
+
+use std::collections::BTreeMap;
 
 use evmodin::{tracing::Tracer, AnalyzedCode, CallKind, Host, Message, Revision, StatusCode};
 
-use eyre::Result;
+impl EvmVersion {
+    /// The evmodin revision of the hard fork
+    pub fn evmodin_cfg(&self) -> Revision {
+        match self {
+            EvmVersion::Frontier => Revision::Frontier,
+            EvmVersion::Istanbul => Revision::Istanbul,
+            EvmVersion::Berlin => Revision::Berlin,
+            EvmVersion::London => Revision::London,
+        }
+    }
+}
 
 // TODO: Check if we can implement this as the base layer of an ethers-provider
 // Middleware stack instead of doing RPC calls.
@@ -15,12 +30,15 @@ pub struct EvmOdin<S, T> {
     pub call_kind: Option<CallKind>,
     pub revision: Revision,
     pub tracer: T,
+    /// The storage of the host when its writes were last taken, which the writes since then
+    /// are found from since the host does not journal them
+    storage: BTreeMap<Address, BTreeMap<H256, H256>>,
 }
 
 impl<S: Host, T: Tracer> EvmOdin<S, T> {
     /// Given a gas limit, vm revision, and initialized host state
     pub fn new(host: S, gas_limit: u64, revision: Revision, tracer: T) -> Self {
-        Self { host, gas_limit, revision, tracer, call_kind: None }
+        Self { host, gas_limit, revision, tracer, call_kind: None, storage: BTreeMap::new() }
     }
 }
 
@@ -31,6 +49,20 @@ pub trait HostExt: Host {
     fn get_code(&self, address: &Address) -> Option<&bytes::Bytes>;
     /// Sets the bytecode at the specified address to the provided value.
     fn set_code(&mut self, address: Address, code: bytes::Bytes);
+    /// Gets the logs which have been emitted against the host since they were last taken.
+    fn take_logs(&mut self) -> Vec<RawLog>;
+    /// Gets the current value of every storage slot of the host.
+    fn storage(&self) -> BTreeMap<Address, BTreeMap<H256, H256>>;
+    /// Sets the balance of the specified address.
+    fn set_balance(&mut self, address: Address, balance: U256);
+    /// Gets the nonce of the specified address.
+    fn get_nonce(&self, address: &Address) -> u64;
+    /// Sets the nonce of the specified address.
+    fn set_nonce(&mut self, address: Address, nonce: u64);
+    /// Sets the `tx.gasprice` of the following calls.
+    fn set_gas_price(&mut self, gas_price: U256);
+    /// Sets the block the following calls get executed in.
+    fn set_block_env(&mut self, env: BlockEnv);
 }
 
 impl<S: HostExt, Tr: Tracer> Evm<S> for EvmOdin<S, Tr> {
@@ -48,6 +80,72 @@ impl<S: HostExt, Tr: Tracer> Evm<S> for EvmOdin<S, Tr> {
         self.host = state;
     }
 
+    fn gas_limit(&self) -> u64 {
+        self.gas_limit
+    }
+
+    fn set_gas_limit(&mut self, gas_limit: u64) {
+        self.gas_limit = gas_limit;
+    }
+
+    fn take_logs(&mut self) -> Vec<RawLog> {
+        self.host.take_logs()
+    }
+
+    fn take_storage_writes(&mut self) -> BTreeMap<Address, BTreeMap<H256, H256>> {
+        let storage = self.host.storage();
+        let mut writes = BTreeMap::new();
+        for (address, slots) in &storage {
+            let before = self.storage.get(address);
+            for (slot, value) in slots {
+                let old = before.and_then(|slots| slots.get(slot)).copied().unwrap_or_default();
+                if old != *value {
+                    writes.entry(*address).or_insert_with(BTreeMap::new).insert(*slot, old);
+                }
+            }
+        }
+        self.storage = storage;
+        writes
+    }
+
+    fn sload(&self, address: Address, slot: H256) -> H256 {
+        self.host.get_storage(address, slot)
+    }
+
+    fn sstore(&mut self, address: Address, slot: H256, value: H256) {
+        self.host.set_storage(address, slot, value);
+    }
+
+    fn set_balance(&mut self, address: Address, balance: U256) {
+        self.host.set_balance(address, balance)
+    }
+
+    fn get_nonce(&self, address: Address) -> u64 {
+        self.host.get_nonce(&address)
+    }
+
+    fn set_nonce(&mut self, address: Address, nonce: u64) {
+        self.host.set_nonce(address, nonce)
+    }
+
+    fn set_tx_env(&mut self, env: TxEnv) -> Result<()> {
+        let base_fee = self.host.get_tx_context().block_base_fee;
+        self.host.set_gas_price(env.effective_gas_price(base_fee)?);
+        Ok(())
+    }
+
+    fn set_block_env(&mut self, env: BlockEnv) -> Result<()> {
+        if env.base_fee > U256::zero() && self.revision < Revision::London {
+            return Err(EvmError::Unsupported("a base fee before London".to_owned()))
+        }
+        self.host.set_block_env(env);
+        Ok(())
+    }
+
+    fn code(&self, address: Address) -> Bytes {
+        self.host.get_code(&address).map(|code| code.to_vec().into()).unwrap_or_default()
+    }
+
     fn initialize_contracts<I: IntoIterator<Item = (Address, Bytes)>>(&mut self, contracts: I) {
         contracts.into_iter().for_each(|(address, bytecode)| {
             self.host.set_code(address, bytecode.0);
@@ -82,9 +180,7 @@ impl<S: HostExt, Tr: Tracer> Evm<S> for EvmOdin<S, Tr> {
         };
 
         // get the bytecode at the host
-        let bytecode = self.host.get_code(&to).ok_or_else(|| {
-            eyre::eyre!("there should be a smart contract at the destination address")
-        })?;
+        let bytecode = self.host.get_code(&to).ok_or(EvmError::MissingContract(to))?;
         let bytecode = AnalyzedCode::analyze(bytecode.as_ref());
         let output =
             bytecode.execute(&mut self.host, &mut self.tracer, None, message, self.revision);
@@ -94,6 +190,54 @@ impl<S: HostExt, Tr: Tracer> Evm<S> for EvmOdin<S, Tr> {
 
         Ok((output.output_data.to_vec().into(), output.status_code, gas))
     }
+
+    fn deploy(
+        &mut self,
+        from: Address,
+        bytecode: Bytes,
+        value: U256,
+    ) -> Result<(Address, Self::ReturnReason, u64)> {
+        let nonce = self.host.get_nonce(&from);
+        let address = ethers::utils::get_contract_address(from, nonce);
+
+        // evmodin only executes the constructor, so the value is transferred here, before it
+        // runs since it may read its balance, and reverted with it
+        let balances = (self.host.get_balance(from), self.host.get_balance(address));
+        if !value.is_zero() {
+            if balances.0 < value {
+                return Ok((address, StatusCode::InsufficientBalance, 0))
+            }
+            self.host.set_balance(from, balances.0 - value);
+            self.host.set_balance(address, balances.1 + value);
+        }
+
+        let message = Message {
+            sender: from,
+            destination: address,
+            depth: 0,
+            kind: CallKind::Create,
+            input_data: Default::default(),
+            value,
+            gas: self.gas_limit as i64,
+            is_static: false,
+        };
+
+        // the constructor returns the runtime bytecode
+        let bytecode = AnalyzedCode::analyze(bytecode.as_ref());
+        let output =
+            bytecode.execute(&mut self.host, &mut self.tracer, None, message, self.revision);
+        if output.status_code == StatusCode::Success {
+            self.host.set_code(address, output.output_data);
+        } else {
+            self.host.set_balance(from, balances.0);
+            self.host.set_balance(address, balances.1);
+        }
+        self.host.set_nonce(from, nonce + 1);
+
+        let gas = self.gas_limit - output.gas_left as u64;
+
+        Ok((address, output.status_code, gas))
+    }
 }
 
 #[cfg(any(test, feature = "evmodin-helpers"))]
@@ -107,19 +251,67 @@ mod helpers {
         }
 
         fn set_code(&mut self, address: Address, bytecode: bytes::Bytes) {
-            let hash = keccak256(&bytecode);
-            self.accounts.insert(
-                address,
-                Account {
-                    nonce: 0,
-                    balance: 0.into(),
-                    code: bytecode,
-                    code_hash: hash.into(),
-                    storage: Default::default(),
-                },
-            );
+            // keep the rest of the account, e.g. the storage written by its constructor
+            let account = account_mut(self, address);
+            account.code_hash = keccak256(&bytecode).into();
+            account.code = bytecode;
+        }
+
+        fn take_logs(&mut self) -> Vec<RawLog> {
+            self.recorded
+                .lock()
+                .logs
+                .drain(..)
+                .map(|log| RawLog { topics: log.topics, data: log.data.to_vec() })
+                .collect()
+        }
+
+        fn storage(&self) -> BTreeMap<Address, BTreeMap<H256, H256>> {
+            self.accounts
+                .iter()
+                .map(|(address, account)| {
+                    let slots = account.storage.iter().map(|(slot, value)| (*slot, value.value));
+                    (*address, slots.collect())
+                })
+                .collect()
+        }
+
+        fn set_balance(&mut self, address: Address, balance: U256) {
+            account_mut(self, address).balance = balance;
+        }
+
+        fn get_nonce(&self, address: &Address) -> u64 {
+            self.accounts.get(address).map(|acc| acc.nonce).unwrap_or_default()
+        }
+
+        fn set_nonce(&mut self, address: Address, nonce: u64) {
+            account_mut(self, address).nonce = nonce;
+        }
+
+        fn set_gas_price(&mut self, gas_price: U256) {
+            self.tx_context.tx_gas_price = gas_price;
+        }
+
+        fn set_block_env(&mut self, env: BlockEnv) {
+            self.tx_context.block_number = env.number;
+            self.tx_context.block_timestamp = env.timestamp;
+            self.tx_context.block_coinbase = env.coinbase;
+            self.tx_context.block_difficulty = env.difficulty;
+            self.tx_context.block_gas_limit = env.gas_limit;
+            self.tx_context.block_base_fee = env.base_fee;
         }
     }
+
+    /// Gets the account at the address, creating an empty one if it does not exist yet
+    fn account_mut(host: &mut MockedHost, address: Address) -> &mut Account {
+        host.accounts.entry(address).or_insert_with(|| Account {
+            nonce: 0,
+            balance: 0.into(),
+            code: Default::default(),
+            code_hash: keccak256(&[]).into(),
+            storage: Default::default(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -142,6 +334,61 @@ mod tests {
         can_call_vm_directly(evm, addr, compiled);
     }
 
+    #[test]
+    fn evmodin_sets_tx_env() {
+        let mut host = MockedHost::default();
+        host.tx_context.block_base_fee = 7.into();
+        let mut evm = EvmOdin::new(host, 12_000_000, Revision::London, NoopTracer);
+
+        let env = TxEnv { max_priority_fee_per_gas: Some(2.into()), ..Default::default() };
+        evm.set_tx_env(env).unwrap();
+        assert_eq!(evm.host.get_tx_context().tx_gas_price, 9.into());
+
+        let underpriced = TxEnv { gas_price: 1.into(), ..Default::default() };
+        assert!(evm.set_tx_env(underpriced).is_err());
+    }
+
+    #[test]
+    fn evmodin_sets_block_env() {
+        let host = MockedHost::default();
+        let mut evm = EvmOdin::new(host, 12_000_000, Revision::Istanbul, NoopTracer);
+
+        let env = BlockEnv { number: 10, timestamp: 42, ..Default::default() };
+        evm.set_block_env(env).unwrap();
+        let context = evm.host.get_tx_context();
+        assert_eq!(context.block_number, 10);
+        assert_eq!(context.block_timestamp, 42);
+        assert_eq!(context.block_gas_limit, 25_000_000);
+
+        let london = BlockEnv { base_fee: 7.into(), ..Default::default() };
+        assert!(evm.set_block_env(london).is_err());
+    }
+
+    #[test]
+    fn evmodin_deploys_with_value() {
+        let host = MockedHost::default();
+        let mut evm = EvmOdin::new(host, 12_000_000, Revision::Istanbul, NoopTracer);
+        let from = Address::repeat_byte(1);
+        evm.set_balance(from, 10.into());
+
+        // PUSH1 0 PUSH1 0 RETURN
+        let (address, status, _) =
+            evm.deploy(from, vec![0x60, 0, 0x60, 0, 0xf3].into(), 3.into()).unwrap();
+        assert_eq!(status, StatusCode::Success);
+        assert_eq!(evm.host.get_balance(from), 7.into());
+        assert_eq!(evm.host.get_balance(address), 3.into());
+
+        // PUSH1 0 PUSH1 0 REVERT
+        let (address, status, _) =
+            evm.deploy(from, vec![0x60, 0, 0x60, 0, 0xfd].into(), 3.into()).unwrap();
+        assert_eq!(status, StatusCode::Revert);
+        assert_eq!(evm.host.get_balance(from), 7.into());
+        assert_eq!(evm.host.get_balance(address), 0.into());
+
+        let (_, status, _) = evm.deploy(from, Bytes::default(), 100.into()).unwrap();
+        assert_eq!(status, StatusCode::InsufficientBalance);
+    }
+
     #[test]
     // TODO: This fails because the cross-contract host does not work.
     #[ignore]
@@ -9,14 +9,199 @@ pub mod evmodin;
 mod blocking_provider;
 pub use blocking_provider::BlockingProvider;
 
+mod error;
+pub use error::EvmError;
+use error::Result;
+
 use ethers::{
-    abi::{Detokenize, Function, Tokenize},
-    core::types::{Address, U256},
+    abi::{Detokenize, Function, RawLog, Tokenize},
+    core::types::{Address, H160, H256, U256},
     prelude::{decode_function_data, encode_function_data, Bytes},
 };
 
 use dapp_utils::get_func;
-use eyre::Result;
+use once_cell::sync::Lazy;
+use std::{collections::BTreeMap, time::Instant};
+
+/// The address `console.log` calls are made to, i.e. "console.log" in ASCII
+pub const CONSOLE_ADDRESS: Address = H160([
+    0, 0, 0, 0, 0, 0, 0, 0, 0, b'c', b'o', b'n', b's', b'o', b'l', b'e', b'.', b'l', b'o', b'g',
+]);
+
+/// The address ds-test's `Hevm` cheatcode interface calls, i.e. the last 20 bytes of
+/// `keccak256("hevm cheat code")`
+pub const HEVM_ADDRESS: Address = H160([
+    0x71, 0x09, 0x70, 0x9e, 0xcf, 0xa9, 0x1a, 0x80, 0x62, 0x6f, 0xf3, 0x98, 0x9d, 0x68, 0xf6, 0x7f,
+    0x5b, 0x1d, 0xd1, 0x2d,
+]);
+
+/// Returns the addresses which have a special meaning to the tests, along with what they are
+/// reserved for. Deploying code to them shadows the precompile or handler living there.
+pub fn reserved_addresses() -> Vec<(Address, &'static str)> {
+    let mut reserved =
+        vec![(HEVM_ADDRESS, "the hevm cheatcodes"), (CONSOLE_ADDRESS, "console.log")];
+    // ecrecover, sha256, ripemd160, identity, modexp, the bn256 curve ops and blake2f
+    reserved.extend((1..=9).map(|i| (Address::from_low_u64_be(i), "a precompile")));
+    reserved
+}
+
+/// Returns what the address is reserved for, see [`reserved_addresses`]
+pub fn reserved_address(address: Address) -> Option<&'static str> {
+    reserved_addresses()
+        .into_iter()
+        .find(|(reserved, _)| *reserved == address)
+        .map(|(_, what)| what)
+}
+
+/// The address of the [deterministic deployment proxy](https://github.com/Arachnid/deterministic-deployment-proxy),
+/// which deploys the creation bytecode following a 32 byte salt in its calldata with `CREATE2`
+pub const CREATE2_DEPLOYER: Address = H160([
+    0x4e, 0x59, 0xb4, 0x48, 0x47, 0xb3, 0x79, 0x57, 0x85, 0x88, 0x92, 0x0c, 0xa7, 0x8f, 0xbf, 0x26,
+    0xc0, 0xb4, 0x95, 0x6c,
+]);
+
+/// The runtime bytecode of the [`CREATE2_DEPLOYER`]
+pub static CREATE2_DEPLOYER_CODE: Lazy<Bytes> = Lazy::new(|| {
+    hex::decode("7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe03601600081602082378035828234f58015156039578182fd5b8082525050506014600cf3")
+        .unwrap()
+        .into()
+});
+
+/// Returns the address which the [`CREATE2_DEPLOYER`] deploys the creation bytecode at
+pub fn create2_address(salt: H256, bytecode: &[u8]) -> Address {
+    ethers::utils::get_create2_address(
+        CREATE2_DEPLOYER,
+        salt.as_bytes().to_vec(),
+        bytecode.to_vec(),
+    )
+}
+
+/// The hard fork whose rules the EVM and the compiler follow
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvmVersion {
+    Frontier,
+    Istanbul,
+    Berlin,
+    London,
+}
+
+impl EvmVersion {
+    /// The matching `--evm-version` of solc. It cannot target Frontier, so Homestead, the
+    /// oldest one it knows, is used instead.
+    pub fn solc_evm_version(&self) -> &'static str {
+        match self {
+            EvmVersion::Frontier => "homestead",
+            EvmVersion::Istanbul => "istanbul",
+            EvmVersion::Berlin => "berlin",
+            EvmVersion::London => "london",
+        }
+    }
+}
+
+impl std::str::FromStr for EvmVersion {
+    type Err = EvmError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "frontier" => EvmVersion::Frontier,
+            "istanbul" => EvmVersion::Istanbul,
+            "berlin" => EvmVersion::Berlin,
+            "london" => EvmVersion::London,
+            _ => return Err(EvmError::Unsupported(format!("the {} hard fork", s))),
+        })
+    }
+}
+
+/// The block the calls get executed in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockEnv {
+    pub number: u64,
+    pub timestamp: u64,
+    pub coinbase: Address,
+    pub difficulty: U256,
+    pub gas_limit: u64,
+    /// The EIP-1559 base fee, zero before London
+    pub base_fee: U256,
+}
+
+impl Default for BlockEnv {
+    /// Block 1 at timestamp 1, since contracts often treat a zero block number or timestamp
+    /// as unset
+    fn default() -> Self {
+        Self {
+            number: 1,
+            timestamp: 1,
+            coinbase: Address::zero(),
+            difficulty: U256::zero(),
+            gas_limit: 25_000_000,
+            base_fee: U256::zero(),
+        }
+    }
+}
+
+/// How the EVM meters the gas of calls and reports it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GasMetering {
+    /// Report the gas before refunds, so that e.g. clearing storage does not make a call look
+    /// cheaper than the code it runs
+    IgnoreRefunds,
+    /// Report the gas minus the refunds, as a transaction would be charged
+    Refunded,
+    /// Run calls without any gas limit and report no gas, for tests which only check logic
+    Disabled,
+}
+
+impl Default for GasMetering {
+    fn default() -> Self {
+        GasMetering::IgnoreRefunds
+    }
+}
+
+impl std::str::FromStr for GasMetering {
+    type Err = EvmError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "ignore-refunds" => GasMetering::IgnoreRefunds,
+            "refunded" => GasMetering::Refunded,
+            "disabled" => GasMetering::Disabled,
+            _ => return Err(EvmError::Unsupported(format!("the {} gas metering", s))),
+        })
+    }
+}
+
+/// The fee parameters of the transactions made by [`Evm::call_raw`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TxEnv {
+    /// The gas price of legacy transactions
+    pub gas_price: U256,
+    /// The EIP-1559 fee cap. Setting it or the priority fee makes the transactions EIP-1559
+    /// ones, which ignore the legacy gas price.
+    pub max_fee_per_gas: Option<U256>,
+    /// The EIP-1559 tip paid to the block's coinbase on top of the base fee
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+impl TxEnv {
+    /// Returns the price per gas the transactions pay in a block with the provided base fee,
+    /// i.e. `tx.gasprice`. EIP-1559 transactions pay the base fee plus their priority fee,
+    /// capped at their max fee.
+    pub fn effective_gas_price(&self, base_fee: U256) -> Result<U256> {
+        if self.max_fee_per_gas.is_none() && self.max_priority_fee_per_gas.is_none() {
+            if self.gas_price < base_fee {
+                return Err(EvmError::FeeBelowBaseFee { fee: self.gas_price, base_fee })
+            }
+            return Ok(self.gas_price)
+        }
+
+        let max_fee = self.max_fee_per_gas.unwrap_or_else(U256::max_value);
+        if max_fee < base_fee {
+            return Err(EvmError::FeeBelowBaseFee { fee: max_fee, base_fee })
+        }
+        let priority_fee = self.max_priority_fee_per_gas.unwrap_or_default();
+        Ok(max_fee.min(base_fee.saturating_add(priority_fee)))
+    }
+}
 
 // TODO: Any reason this should be an async trait?
 /// Low-level abstraction layer for interfacing with various EVMs. Once instantiated, one
@@ -39,6 +224,61 @@ pub trait Evm<State> {
     /// Resets the EVM's state to the provided value
     fn reset(&mut self, state: State);
 
+    /// Gets the gas limit which each call is executed with
+    fn gas_limit(&self) -> u64;
+
+    /// Sets the gas limit which each call is executed with
+    fn set_gas_limit(&mut self, gas_limit: u64);
+
+    /// Interrupts the calls which are still running once the deadline passed, making them
+    /// fail. `None` lifts the deadline. EVMs which cannot interrupt a call ignore it.
+    fn set_deadline(&mut self, _deadline: Option<Instant>) {}
+
+    /// Returns the logs which have been emitted against the current state since they were
+    /// last taken, in the order in which they were emitted, and forgets them. Logs of reverted
+    /// calls are not included.
+    fn take_logs(&mut self) -> Vec<RawLog>;
+
+    /// Returns the storage slots which have been written to against the current state since
+    /// they were last taken, keyed by address, along with the value each of them had before,
+    /// and forgets them
+    fn take_storage_writes(&mut self) -> BTreeMap<Address, BTreeMap<H256, H256>>;
+
+    /// Reads the value of a storage slot
+    fn sload(&self, address: Address, slot: H256) -> H256;
+
+    /// Writes the value of a storage slot
+    fn sstore(&mut self, address: Address, slot: H256, value: H256);
+
+    /// Sets the balance of an account
+    fn set_balance(&mut self, address: Address, balance: U256);
+
+    /// Gets the nonce of an account, i.e. how many transactions it sent or, for contracts, how
+    /// many contracts it created
+    fn get_nonce(&self, address: Address) -> u64;
+
+    /// Sets the nonce of an account
+    fn set_nonce(&mut self, address: Address, nonce: u64);
+
+    /// Sets the fee parameters of the following calls. Their effective gas price is derived
+    /// from the block's base fee, see [`TxEnv::effective_gas_price`].
+    fn set_tx_env(&mut self, env: TxEnv) -> Result<()>;
+
+    /// Sets the block the following calls get executed in
+    fn set_block_env(&mut self, env: BlockEnv) -> Result<()>;
+
+    /// Sets how the gas of the following calls gets metered and reported. EVMs which always
+    /// report the gas before refunds only accept the default.
+    fn set_gas_metering(&mut self, gas_metering: GasMetering) -> Result<()> {
+        if gas_metering != GasMetering::default() {
+            return Err(EvmError::Unsupported(format!("{:?} gas metering", gas_metering)))
+        }
+        Ok(())
+    }
+
+    /// Gets the code deployed at an address, empty if it is not a contract
+    fn code(&self, address: Address) -> Bytes;
+
     /// Executes the specified EVM call against the state
     // TODO: Should we just make this take a `TransactionRequest` or other more
     // ergonomic type?
@@ -62,6 +302,11 @@ pub trait Evm<State> {
         Ok((retdata, status, gas))
     }
 
+    /// Executes the call with raw calldata. The returned gas is what the call's execution
+    /// consumed, without the base transaction and calldata costs. Unless the EVM is configured
+    /// otherwise (see e.g. the sputnik `GasMetering`), refunds are not deducted from it so that
+    /// clearing storage does not make a test look cheaper than the code it runs.
+    /// Static calls fail on any state change and leave the state untouched.
     fn call_raw(
         &mut self,
         from: Address,
@@ -71,6 +316,35 @@ pub trait Evm<State> {
         is_static: bool,
     ) -> Result<(Bytes, Self::ReturnReason, u64)>;
 
+    /// Deploys the contract with the provided creation bytecode (i.e. including constructor
+    /// arguments) from the sender, returning the address it was deployed at. The address is
+    /// derived from the sender's nonce, like for `CREATE`.
+    fn deploy(
+        &mut self,
+        from: Address,
+        bytecode: Bytes,
+        value: U256,
+    ) -> Result<(Address, Self::ReturnReason, u64)>;
+
+    /// Deploys the contract through the [`CREATE2_DEPLOYER`], so that its address only depends
+    /// on the salt and the creation bytecode, see [`create2_address`]. The deployer gets
+    /// installed first if it is missing from the state.
+    fn deploy2(
+        &mut self,
+        from: Address,
+        salt: H256,
+        bytecode: Bytes,
+        value: U256,
+    ) -> Result<(Address, Self::ReturnReason, u64)> {
+        if self.code(CREATE2_DEPLOYER).as_ref().is_empty() {
+            self.initialize_contracts(vec![(CREATE2_DEPLOYER, CREATE2_DEPLOYER_CODE.clone())]);
+        }
+        let calldata = [salt.as_bytes(), bytecode.as_ref()].concat();
+        let (_, reason, gas) =
+            self.call_raw(from, CREATE2_DEPLOYER, calldata.into(), value, false)?;
+        Ok((create2_address(salt, &bytecode), reason, gas))
+    }
+
     /// Runs the `setUp()` function call to instantiate the contract's state
     fn setup(&mut self, address: Address) -> Result<()> {
         let (_, _, _) = self.call::<(), _>(
@@ -119,9 +393,38 @@ pub trait Evm<State> {
             Self::is_success(reason)
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_evm_versions() {
+        assert_eq!("London".parse::<EvmVersion>().unwrap(), EvmVersion::London);
+        assert_eq!("frontier".parse::<EvmVersion>().unwrap().solc_evm_version(), "homestead");
+        assert!("shanghai".parse::<EvmVersion>().is_err());
+    }
+
+    #[test]
+    fn effective_gas_price() {
+        let legacy = TxEnv { gas_price: 10.into(), ..Default::default() };
+        assert_eq!(legacy.effective_gas_price(5.into()).unwrap(), 10.into());
+        assert!(legacy.effective_gas_price(11.into()).is_err());
 
-    // TODO: Should we add a "deploy contract" function as well, or should we assume that
-    // the EVM is instantiated with a DB that includes any needed contracts?
+        let eip1559 = TxEnv {
+            gas_price: 1000.into(),
+            max_fee_per_gas: Some(20.into()),
+            max_priority_fee_per_gas: Some(3.into()),
+        };
+        assert_eq!(eip1559.effective_gas_price(5.into()).unwrap(), 8.into());
+        // the priority fee gets cut once the max fee is reached
+        assert_eq!(eip1559.effective_gas_price(19.into()).unwrap(), 20.into());
+        assert!(eip1559.effective_gas_price(21.into()).is_err());
+
+        let tip_only = TxEnv { max_priority_fee_per_gas: Some(2.into()), ..Default::default() };
+        assert_eq!(tip_only.effective_gas_price(7.into()).unwrap(), 9.into());
+    }
 }
 
 // Test helpers which are generic over EVM implementation
@@ -0,0 +1,152 @@
+//! Support for Hardhat's `console.log`. Calls to the console address are handled by a
+//! precompile which re-emits the formatted message as a DSTest `log(string)` event, so that it
+//! shows up in the logs of the test.
+
+use ethers::{
+    abi::{ParamType, Token},
+    types::Address,
+    utils::{id, keccak256, to_checksum},
+};
+use once_cell::sync::Lazy;
+use sputnik::{
+    backend::Log,
+    executor::{PrecompileFailure, PrecompileOutput},
+    Context, ExitSucceed,
+};
+use std::collections::HashMap;
+
+pub use crate::CONSOLE_ADDRESS;
+
+/// The parameter types of all the `console.log` overloads, keyed by selector
+static OVERLOADS: Lazy<HashMap<[u8; 4], Vec<ParamType>>> = Lazy::new(|| {
+    let mut signatures = vec![
+        ("log".to_owned(), vec![]),
+        ("logInt".to_owned(), vec!["int"]),
+        ("logUint".to_owned(), vec!["uint"]),
+        ("logString".to_owned(), vec!["string"]),
+        ("logBool".to_owned(), vec!["bool"]),
+        ("logAddress".to_owned(), vec!["address"]),
+        ("logBytes".to_owned(), vec!["bytes"]),
+    ];
+    let fixed_bytes = (1..=32).map(|n| format!("bytes{}", n)).collect::<Vec<_>>();
+    for (n, kind) in (1..=32).zip(&fixed_bytes) {
+        signatures.push((format!("logBytes{}", n), vec![kind.as_str()]));
+    }
+
+    // `log` is overloaded for every combination of up to 4 of these
+    let kinds = ["uint", "string", "bool", "address"];
+    let mut combinations: Vec<Vec<&str>> = vec![vec![]];
+    for _ in 0..4 {
+        combinations = combinations
+            .iter()
+            .flat_map(|prefix| {
+                kinds.iter().map(move |kind| {
+                    let mut params = prefix.clone();
+                    params.push(kind);
+                    params
+                })
+            })
+            .collect();
+        signatures.extend(combinations.iter().map(|params| ("log".to_owned(), params.clone())));
+    }
+
+    signatures
+        .into_iter()
+        .map(|(name, params)| {
+            // NB: console.sol computes the selectors with `uint` instead of `uint256`
+            let selector = id(format!("{}({})", name, params.join(",")));
+            (selector, params.into_iter().map(param_type).collect())
+        })
+        .collect()
+});
+
+fn param_type(kind: &str) -> ParamType {
+    match kind {
+        "uint" => ParamType::Uint(256),
+        "int" => ParamType::Int(256),
+        "string" => ParamType::String,
+        "bool" => ParamType::Bool,
+        "address" => ParamType::Address,
+        "bytes" => ParamType::Bytes,
+        kind => ParamType::FixedBytes(kind["bytes".len()..].parse().expect("invalid bytesN")),
+    }
+}
+
+/// Precompile handling the `console.log` calls. Unknown selectors and malformed calldata are
+/// ignored, so that logging can never make a test fail.
+pub fn console_log(
+    input: &[u8],
+    _gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> Result<PrecompileOutput, PrecompileFailure> {
+    let logs = format(input)
+        .map(|message| {
+            vec![Log {
+                address: CONSOLE_ADDRESS,
+                topics: vec![keccak256("log(string)").into()],
+                data: ethers::abi::encode(&[Token::String(message)]),
+            }]
+        })
+        .unwrap_or_default();
+
+    Ok(PrecompileOutput { exit_status: ExitSucceed::Returned, cost: 0, output: Vec::new(), logs })
+}
+
+/// Decodes the calldata of a `console.log` call and joins its arguments with spaces
+fn format(input: &[u8]) -> Option<String> {
+    if input.len() < 4 {
+        return None
+    }
+    let mut selector = [0; 4];
+    selector.copy_from_slice(&input[..4]);
+    let params = OVERLOADS.get(&selector)?;
+    let tokens = ethers::abi::decode(params, &input[4..]).ok()?;
+
+    let args = tokens
+        .into_iter()
+        .map(|token| match token {
+            Token::Address(address) => to_checksum(&address, None),
+            Token::Int(num) => ethers::types::I256::from_raw(num).to_string(),
+            Token::Uint(num) => num.to_string(),
+            Token::Bytes(bytes) | Token::FixedBytes(bytes) => format!("0x{}", hex::encode(bytes)),
+            token => token.to_string(),
+        })
+        .collect::<Vec<_>>();
+    Some(args.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U256;
+
+    fn call(signature: &str, args: &[Token]) -> Vec<Log> {
+        let mut input = id(signature).to_vec();
+        input.extend(ethers::abi::encode(args));
+        let context = Context {
+            address: CONSOLE_ADDRESS,
+            caller: Address::zero(),
+            apparent_value: U256::zero(),
+        };
+        console_log(&input, None, &context, false).unwrap().logs
+    }
+
+    #[test]
+    fn formats_console_logs() {
+        assert_eq!(OVERLOADS.len(), 1 + 6 + 32 + 4 + 16 + 64 + 256);
+
+        let logs = call("log(string,uint)", &[Token::String("gm".into()), Token::Uint(1.into())]);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].topics, vec![keccak256("log(string)").into()]);
+        let message = ethers::abi::decode(&[ParamType::String], &logs[0].data).unwrap();
+        assert_eq!(message, vec![Token::String("gm 1".to_owned())]);
+
+        let logs = call("logBytes2(bytes2)", &[Token::FixedBytes(vec![0xab, 0xcd])]);
+        let message = ethers::abi::decode(&[ParamType::String], &logs[0].data).unwrap();
+        assert_eq!(message, vec![Token::String("0xabcd".to_owned())]);
+
+        // unknown calls are ignored
+        assert!(call("foo()", &[]).is_empty());
+    }
+}
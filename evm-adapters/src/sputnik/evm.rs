@@ -1,23 +1,101 @@
-use crate::Evm;
-
-use ethers::types::{Address, Bytes, U256};
+use crate::{error::Result, BlockEnv, Evm, EvmError, GasMetering, TxEnv};
+
+use ethers::{
+    abi::RawLog,
+    types::{
+        transaction::eip2930::{AccessList, AccessListItem},
+        Address, Bytes, H256, U256,
+    },
+};
 
 use sputnik::{
     backend::{Backend, MemoryAccount},
-    executor::{MemoryStackState, StackExecutor, StackState, StackSubstateMetadata},
-    Config, ExitReason, Handler,
+    executor::{
+        MemoryStackState, PrecompileFailure, PrecompileOutput, StackExecutor, StackState,
+        StackSubstateMetadata,
+    },
+    Config, Context, CreateScheme, ExitReason, Handler,
 };
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, time::Instant};
 
-use eyre::Result;
+use super::{console, JournaledState};
 
 pub type MemoryState = BTreeMap<Address, MemoryAccount>;
 
+/// A precompiled contract, see [`StackExecutor::new_with_precompile`]
+pub type PrecompileFn =
+    fn(&[u8], Option<u64>, &Context, bool) -> Result<PrecompileOutput, PrecompileFailure>;
+
+/// The precompiles every executor is instantiated with:
+/// - `console.log`, see [`console`]
+///
+/// The standard precompiles (ecrecover, sha256, ...) are not among them. Tests which need them,
+/// or a cheap deterministic stand-in, e.g. an ecrecover accepting a fixed signature, register
+/// them at their addresses through [`Executor::with_precompiles`] or [`Executor::precompile`],
+/// which also override the defaults above.
+pub fn precompiles() -> BTreeMap<Address, PrecompileFn> {
+    let mut precompiles = BTreeMap::new();
+    precompiles.insert(console::CONSOLE_ADDRESS, console::console_log as PrecompileFn);
+    precompiles
+}
+
+/// The access list a call would benefit from, see [`Executor::access_list`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratedAccessList {
+    pub access_list: AccessList,
+    /// The gas the transaction uses without the access list
+    pub gas_used: u64,
+    /// The gas the transaction uses with the access list
+    pub gas_used_with_access_list: u64,
+    /// How the call exited. The access list of a reverted call covers what it accessed up
+    /// until the revert.
+    pub reason: ExitReason,
+}
+
 // TODO: Check if we can implement this as the base layer of an ethers-provider
 // Middleware stack instead of doing RPC calls.
 pub struct Executor<'a, S> {
     pub executor: StackExecutor<'a, S>,
     pub gas_limit: u64,
+    pub gas_metering: GasMetering,
+    /// The precompiles the executor was instantiated with, kept around since the inner
+    /// executor does not expose them and they have to be carried over to its copies
+    precompiles: BTreeMap<Address, PrecompileFn>,
+}
+
+/// Helper trait for exposing additional functionality over Sputnik's stack states
+pub trait StackStateExt<'a>: StackState<'a> {
+    /// Returns the logs which have been emitted against the state since they were last taken
+    fn take_logs(&mut self) -> Vec<RawLog>;
+
+    /// Returns the storage slots which have been written to against the state since they were
+    /// last taken, along with the value each of them had before
+    fn take_storage_writes(&mut self) -> BTreeMap<Address, BTreeMap<H256, H256>>;
+
+    /// Sets the balance of an account
+    fn set_balance(&mut self, address: Address, balance: U256);
+
+    /// Makes the calls still running against the state once the deadline passed run out of gas
+    fn set_deadline(&mut self, deadline: Option<Instant>);
+}
+
+impl<'a, B: Backend> StackStateExt<'a> for JournaledState<MemoryStackState<'a, 'a, B>> {
+    fn take_logs(&mut self) -> Vec<RawLog> {
+        JournaledState::take_logs(self)
+    }
+
+    fn take_storage_writes(&mut self) -> BTreeMap<Address, BTreeMap<H256, H256>> {
+        JournaledState::take_storage_writes(self)
+    }
+
+    fn set_balance(&mut self, address: Address, balance: U256) {
+        self.reset_balance(address);
+        self.inner_mut().deposit(address, balance);
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        JournaledState::set_deadline(self, deadline)
+    }
 }
 
 // Manual implementation of `Clone` for Clone-able StackStates (typically when the Backend
@@ -28,28 +106,167 @@ impl<'a, S: StackState<'a> + Clone> Clone for Executor<'a, S> {
     fn clone(&self) -> Self {
         Self {
             gas_limit: self.gas_limit,
+            gas_metering: self.gas_metering,
             executor: StackExecutor::new_with_precompile(
                 self.executor.state().clone(),
                 self.executor.config(),
-                Default::default(),
+                self.precompiles.clone(),
             ),
+            precompiles: self.precompiles.clone(),
         }
     }
 }
 
+impl<'a, S: StackState<'a> + Clone> Executor<'a, S> {
+    /// Registers a precompile at the address, keeping a copy of the current state
+    pub fn precompile(mut self, address: Address, precompile: PrecompileFn) -> Self {
+        self.precompiles.insert(address, precompile);
+        self.executor = StackExecutor::new_with_precompile(
+            self.executor.state().clone(),
+            self.executor.config(),
+            self.precompiles.clone(),
+        );
+        self
+    }
+}
+
+impl<'a, S: StackState<'a>> Executor<'a, S> {
+    /// Sets how gas gets metered and reported
+    pub fn gas_metering(mut self, gas_metering: GasMetering) -> Self {
+        self.gas_metering = gas_metering;
+        self
+    }
+
+    /// The gas limit calls actually run with, which is unbounded without metering
+    fn metered_gas_limit(&self) -> u64 {
+        match self.gas_metering {
+            GasMetering::Disabled => u64::MAX,
+            _ => self.gas_limit,
+        }
+    }
+
+    /// Refills the root gasometer up to the gas limit. Sputnik charges every transaction
+    /// against it without refilling it, so each call would otherwise be capped by the gas the
+    /// previous ones left over instead of the current gas limit.
+    fn reset_gasometer(&mut self) {
+        let config = self.executor.config();
+        *self.executor.state_mut().metadata_mut() =
+            StackSubstateMetadata::new(self.metered_gas_limit(), config);
+    }
+
+    /// The gas used since `gas_before` without the transaction's base and data costs, which
+    /// has to be read before leaving the substate the call ran in
+    fn reported_gas(&self, gas_before: U256, data: &[u8]) -> u64 {
+        if self.gas_metering == GasMetering::Disabled {
+            return 0
+        }
+        let gas_after = self.executor.gas_left();
+        let gas = dapp_utils::remove_extra_costs(gas_before - gas_after, data).as_u64();
+        if self.gas_metering == GasMetering::Refunded {
+            // the executor deducts the capped refund from the gas of the whole transaction
+            let total = self.metered_gas_limit() - gas_after.as_u64();
+            return gas.saturating_sub(total - self.executor.used_gas())
+        }
+        gas
+    }
+}
+
 // Concrete implementation over the in-memory backend
-impl<'a, B: Backend> Executor<'a, MemoryStackState<'a, 'a, B>> {
+impl<'a, B: Backend> Executor<'a, JournaledState<MemoryStackState<'a, 'a, B>>> {
     /// Given a gas limit, vm version, initial chain configuration and initial state
     // TOOD: See if we can make lifetimes better here
     pub fn new(gas_limit: u64, config: &'a Config, backend: &'a B) -> Self {
+        Self::with_precompiles(gas_limit, config, backend, BTreeMap::new())
+    }
+
+    /// Same as [`Executor::new`], but also registers the provided precompiles, e.g. the ones
+    /// of an L2 the contracts get deployed to. They take precedence over the default
+    /// [`precompiles`] living at the same address.
+    pub fn with_precompiles(
+        gas_limit: u64,
+        config: &'a Config,
+        backend: &'a B,
+        extra: BTreeMap<Address, PrecompileFn>,
+    ) -> Self {
         // setup gasometer
         let metadata = StackSubstateMetadata::new(gas_limit, config);
         // setup state
-        let state = MemoryStackState::new(metadata, backend);
+        let state = JournaledState::new(MemoryStackState::new(metadata, backend));
         // setup executor
-        let executor = StackExecutor::new_with_precompile(state, config, Default::default());
+        let mut precompiles = precompiles();
+        precompiles.extend(extra);
+        let executor = StackExecutor::new_with_precompile(state, config, precompiles.clone());
 
-        Self { executor, gas_limit }
+        Self { executor, gas_limit, gas_metering: GasMetering::default(), precompiles }
+    }
+}
+
+// Running calls on a copy of the state requires a clone-able backend
+impl<'a, B: Backend + Clone> Executor<'a, JournaledState<MemoryStackState<'a, 'a, B>>> {
+    /// Executes the call on a copy of the state and returns the addresses and storage slots it
+    /// accessed as an EIP-2930 access list, along with the gas it uses with and without it.
+    /// Addresses which are warm anyway (the sender, the callee and the precompiles) are only
+    /// listed if some of their slots got accessed.
+    ///
+    /// Accesses are only tracked from Berlin on, so this fails for earlier configs.
+    pub fn access_list(
+        &self,
+        from: Address,
+        to: Address,
+        calldata: Bytes,
+    ) -> Result<GeneratedAccessList> {
+        let config = self.executor.config();
+        if !config.increase_state_access_gas {
+            return Err(EvmError::Unsupported("tracking state accesses before Berlin".to_owned()))
+        }
+        let run = |access_list: Vec<(Address, Vec<H256>)>| {
+            let mut state = self.executor.state().clone();
+            // start from a fresh substate so that the accesses of previous calls do not count
+            *state.metadata_mut() = StackSubstateMetadata::new(self.gas_limit, config);
+            let mut executor =
+                StackExecutor::new_with_precompile(state, config, self.precompiles.clone());
+            let (reason, _) = executor.transact_call(
+                from,
+                to,
+                U256::zero(),
+                calldata.to_vec(),
+                self.gas_limit,
+                access_list,
+            );
+            (reason, executor)
+        };
+
+        let (reason, executor) = run(Vec::new());
+        let accessed = executor.state().metadata().accessed().as_ref().ok_or_else(|| {
+            EvmError::Unsupported("tracking state accesses with this config".to_owned())
+        })?;
+        let mut slots: BTreeMap<Address, Vec<H256>> = BTreeMap::new();
+        for (address, slot) in &accessed.accessed_storage {
+            slots.entry(*address).or_default().push(*slot);
+        }
+        for address in &accessed.accessed_addresses {
+            let warm =
+                *address == from || *address == to || crate::reserved_address(*address).is_some();
+            if !warm {
+                slots.entry(*address).or_default();
+            }
+        }
+        let entries = slots.into_iter().collect::<Vec<_>>();
+
+        let gas_used = executor.used_gas();
+        let (_, with_access_list) = run(entries.clone());
+        let access_list = AccessList(
+            entries
+                .into_iter()
+                .map(|(address, storage_keys)| AccessListItem { address, storage_keys })
+                .collect(),
+        );
+        Ok(GeneratedAccessList {
+            access_list,
+            gas_used,
+            gas_used_with_access_list: with_access_list.used_gas(),
+            reason,
+        })
     }
 }
 
@@ -60,7 +277,7 @@ impl<'a, B: Backend> Executor<'a, MemoryStackState<'a, 'a, B>> {
 // to be generic across implementations, but we don't want to make it a user-controlled generic.
 impl<'a, S> Evm<S> for Executor<'a, S>
 where
-    S: StackState<'a>,
+    S: StackStateExt<'a>,
 {
     type ReturnReason = ExitReason;
 
@@ -77,6 +294,101 @@ where
         *_state = state;
     }
 
+    fn gas_limit(&self) -> u64 {
+        self.gas_limit
+    }
+
+    /// The new limit applies from the next call on, which starts with a refilled gasometer
+    fn set_gas_limit(&mut self, gas_limit: u64) {
+        self.gas_limit = gas_limit;
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.executor.state_mut().set_deadline(deadline)
+    }
+
+    fn take_logs(&mut self) -> Vec<RawLog> {
+        self.executor.state_mut().take_logs()
+    }
+
+    fn take_storage_writes(&mut self) -> BTreeMap<Address, BTreeMap<H256, H256>> {
+        self.executor.state_mut().take_storage_writes()
+    }
+
+    fn sload(&self, address: Address, slot: H256) -> H256 {
+        Backend::storage(self.executor.state(), address, slot)
+    }
+
+    fn sstore(&mut self, address: Address, slot: H256, value: H256) {
+        self.executor.state_mut().set_storage(address, slot, value)
+    }
+
+    fn set_balance(&mut self, address: Address, balance: U256) {
+        self.executor.state_mut().set_balance(address, balance)
+    }
+
+    fn get_nonce(&self, address: Address) -> u64 {
+        self.executor.state().basic(address).nonce.as_u64()
+    }
+
+    /// Sputnik's states can only increment nonces, so the nonce cannot be lowered
+    fn set_nonce(&mut self, address: Address, nonce: u64) {
+        let state = self.executor.state_mut();
+        let mut current = state.basic(address).nonce;
+        if current > nonce.into() {
+            tracing::warn!(?address, %current, nonce, "cannot decrease the nonce of an account");
+        }
+        while current < nonce.into() {
+            state.inc_nonce(address);
+            current += U256::one();
+        }
+    }
+
+    /// Sputnik reads the gas price from the backend's vicinity and has no base fee before
+    /// London, so this only checks that the environment matches the vicinity
+    fn set_tx_env(&mut self, env: TxEnv) -> Result<()> {
+        let gas_price = env.effective_gas_price(U256::zero())?;
+        if gas_price != self.executor.state().gas_price() {
+            return Err(EvmError::Unsupported(format!(
+                "changing the gas price to {} after instantiation",
+                gas_price
+            )))
+        }
+        Ok(())
+    }
+
+    fn set_block_env(&mut self, env: BlockEnv) -> Result<()> {
+        if env.base_fee > U256::zero() {
+            return Err(EvmError::Unsupported("the London base fee".to_owned()))
+        }
+        // the block comes from the backend, which cannot be changed through the executor
+        let state = self.executor.state();
+        let current = BlockEnv {
+            number: state.block_number().as_u64(),
+            timestamp: state.block_timestamp().as_u64(),
+            coinbase: state.block_coinbase(),
+            difficulty: state.block_difficulty(),
+            gas_limit: state.block_gas_limit().as_u64(),
+            base_fee: U256::zero(),
+        };
+        if env != current {
+            return Err(EvmError::Unsupported(format!(
+                "changing the block to {:?} after instantiation",
+                env
+            )))
+        }
+        Ok(())
+    }
+
+    fn set_gas_metering(&mut self, gas_metering: GasMetering) -> Result<()> {
+        self.gas_metering = gas_metering;
+        Ok(())
+    }
+
+    fn code(&self, address: Address) -> Bytes {
+        self.executor.state().code(address).into()
+    }
+
     /// given an iterator of contract address to contract bytecode, initializes
     /// the state with the contract deployed at the specified address
     fn initialize_contracts<T: IntoIterator<Item = (Address, Bytes)>>(&mut self, contracts: T) {
@@ -97,17 +409,53 @@ where
         to: Address,
         calldata: Bytes,
         value: U256,
-        _is_static: bool,
+        is_static: bool,
     ) -> Result<(Bytes, ExitReason, u64)> {
+        self.reset_gasometer();
+        // transactions cannot be static, so static calls run in a static substate instead,
+        // which makes any state change fail and gets discarded afterwards
+        let gas_limit = self.metered_gas_limit();
+        if is_static {
+            self.executor.state_mut().enter(gas_limit, true);
+        }
         let gas_before = self.executor.gas_left();
+        let nonce_before = self.get_nonce(from);
 
         let (status, retdata) =
-            self.executor.transact_call(from, to, value, calldata.to_vec(), self.gas_limit, vec![]);
+            self.executor.transact_call(from, to, value, calldata.to_vec(), gas_limit, vec![]);
 
-        let gas_after = self.executor.gas_left();
-        let gas = dapp_utils::remove_extra_costs(gas_before - gas_after, calldata.as_ref());
+        // each call is a transaction of the sender, so its nonce goes up by one even if the call
+        // reverted, unless the executor already took care of it
+        if !is_static && self.get_nonce(from) == nonce_before {
+            self.executor.state_mut().inc_nonce(from);
+        }
+
+        let gas = self.reported_gas(gas_before, calldata.as_ref());
+        if is_static {
+            // discarding only fails without a parent substate
+            let _ = self.executor.state_mut().exit_discard();
+        }
+
+        Ok((retdata.into(), status, gas))
+    }
+
+    fn deploy(
+        &mut self,
+        from: Address,
+        bytecode: Bytes,
+        value: U256,
+    ) -> Result<(Address, ExitReason, u64)> {
+        // the address depends on the nonce before the creation
+        let address = self.executor.create_address(CreateScheme::Legacy { caller: from });
+        self.reset_gasometer();
+        let gas_before = self.executor.gas_left();
+
+        let gas_limit = self.metered_gas_limit();
+        let status =
+            self.executor.transact_create(from, value, bytecode.to_vec(), gas_limit, vec![]);
+        let gas = self.reported_gas(gas_before, bytecode.as_ref());
 
-        Ok((retdata.into(), status, gas.as_u64()))
+        Ok((address, status, gas))
     }
 }
 
@@ -115,14 +463,29 @@ where
 pub mod helpers {
     use super::*;
     use ethers::types::H160;
+    use once_cell::sync::Lazy;
     use sputnik::backend::{MemoryBackend, MemoryVicinity};
 
+    static CFG: Lazy<Config> = Lazy::new(Config::istanbul);
+    static VICINITY: Lazy<MemoryVicinity> = Lazy::new(new_vicinity);
+    static BACKEND: Lazy<MemoryBackend<'static>> =
+        Lazy::new(|| new_backend(&*VICINITY, Default::default()));
+
+    /// The executor tests run against: Istanbul rules, the default vicinity and an empty
+    /// backend, with a 12M gas limit
+    pub fn new_executor(
+    ) -> Executor<'static, JournaledState<MemoryStackState<'static, 'static, MemoryBackend<'static>>>>
+    {
+        Executor::new(12_000_000, &*CFG, &*BACKEND)
+    }
+
     pub fn new_backend(vicinity: &MemoryVicinity, state: MemoryState) -> MemoryBackend<'_> {
         MemoryBackend::new(vicinity, state)
     }
 
+    /// The vicinity of the default [`BlockEnv`]
     pub fn new_vicinity() -> MemoryVicinity {
-        MemoryVicinity {
+        let mut vicinity = MemoryVicinity {
             gas_price: U256::zero(),
             origin: H160::default(),
             block_hashes: Vec::new(),
@@ -132,32 +495,31 @@ pub mod helpers {
             block_difficulty: Default::default(),
             block_gas_limit: Default::default(),
             chain_id: U256::one(),
-        }
+        };
+        BlockEnv::default().fill_vicinity(&mut vicinity);
+        vicinity
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        helpers::{new_backend, new_vicinity},
+        helpers::{new_backend, new_executor, new_vicinity},
         *,
     };
     use crate::test_helpers::{can_call_vm_directly, solidity_unit_test, COMPILED};
     use dapp_utils::{decode_revert, get_func};
 
     use ethers::utils::id;
-    use sputnik::{ExitReason, ExitRevert, ExitSucceed};
+    use sputnik::{backend::MemoryVicinity, ExitReason, ExitRevert, ExitSucceed};
 
     #[test]
     fn sputnik_can_call_vm_directly() {
-        let cfg = Config::istanbul();
         let compiled = COMPILED.get("Greeter").expect("could not find contract");
 
         let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
 
-        let vicinity = new_vicinity();
-        let backend = new_backend(&vicinity, Default::default());
-        let mut evm = Executor::new(12_000_000, &cfg, &backend);
+        let mut evm = new_executor();
         evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
 
         can_call_vm_directly(evm, addr, compiled);
@@ -165,33 +527,327 @@ mod tests {
 
     #[test]
     fn sputnik_solidity_unit_test() {
-        let cfg = Config::istanbul();
-
         let compiled = COMPILED.get("GreeterTest").expect("could not find contract");
 
         let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
 
+        let mut evm = new_executor();
+        evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+
+        solidity_unit_test(evm, addr, compiled);
+    }
+
+    #[test]
+    fn records_storage_writes() {
+        let compiled = COMPILED.get("Greeter").expect("could not find contract");
+        let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+
+        let greet = get_func("function greet(string greeting) external").unwrap();
+
+        let mut evm = new_executor();
+        evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+        assert!(evm.take_storage_writes().is_empty());
+
+        evm.call::<(), _>(Address::zero(), addr, &greet, "hi".to_owned(), 0.into()).unwrap();
+
+        // short strings are stored in a single slot, which was empty before
+        let writes = evm.take_storage_writes();
+        assert_eq!(writes.len(), 1);
+        let (slot, old) = writes[&addr].iter().next().map(|(slot, old)| (*slot, *old)).unwrap();
+        assert_eq!(old, H256::zero());
+        let hi = evm.sload(addr, slot);
+        assert_ne!(hi, H256::zero());
+
+        // the value before the first write is kept, and taken writes are forgotten
+        evm.call::<(), _>(Address::zero(), addr, &greet, "gm".to_owned(), 0.into()).unwrap();
+        evm.call::<(), _>(Address::zero(), addr, &greet, "yo".to_owned(), 0.into()).unwrap();
+        assert_eq!(evm.take_storage_writes()[&addr][&slot], hi);
+        assert!(evm.take_storage_writes().is_empty());
+    }
+
+    #[test]
+    fn journals_logs() {
+        let logger = Address::repeat_byte(1);
+        let reverter = Address::repeat_byte(2);
+        let mut evm = new_executor();
+        // PUSH1 0 PUSH1 0 LOG0, followed by STOP or by PUSH1 0 PUSH1 0 REVERT
+        evm.initialize_contracts(vec![
+            (logger, vec![0x60, 0, 0x60, 0, 0xa0, 0x00].into()),
+            (reverter, vec![0x60, 0, 0x60, 0, 0xa0, 0x60, 0, 0x60, 0, 0xfd].into()),
+        ]);
+
+        evm.call_raw(Address::zero(), logger, Bytes::default(), 0.into(), false).unwrap();
+        // the logs of reverted and static calls are dropped
+        let (_, status, _) =
+            evm.call_raw(Address::zero(), reverter, Bytes::default(), 0.into(), false).unwrap();
+        assert_eq!(status, ExitReason::Revert(ExitRevert::Reverted));
+        evm.call_raw(Address::zero(), logger, Bytes::default(), 0.into(), true).unwrap();
+        assert_eq!(evm.take_logs(), vec![RawLog { topics: Vec::new(), data: Vec::new() }]);
+
+        // taking the logs forgets them
+        evm.call_raw(Address::zero(), logger, Bytes::default(), 0.into(), false).unwrap();
+        assert_eq!(evm.take_logs().len(), 1);
+        assert!(evm.take_logs().is_empty());
+    }
+
+    #[test]
+    fn can_modify_state() {
+        let compiled = COMPILED.get("Greeter").expect("could not find contract");
+        let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+
+        let mut evm = new_executor();
+        evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+        assert_eq!(evm.code(addr), compiled.runtime_bytecode);
+
+        let slot = H256::from_low_u64_be(1);
+        evm.sstore(addr, slot, H256::repeat_byte(2));
+        assert_eq!(evm.sload(addr, slot), H256::repeat_byte(2));
+
+        let account = Address::repeat_byte(3);
+        evm.set_balance(account, 100.into());
+        evm.set_balance(account, 10.into());
+        evm.set_nonce(account, 5);
+        let basic = evm.executor.state().basic(account);
+        assert_eq!(basic.balance, 10.into());
+        assert_eq!(basic.nonce, 5.into());
+    }
+
+    #[test]
+    fn generates_access_lists() {
+        let cfg = Config::berlin();
+        let compiled = COMPILED.get("GreeterTest").expect("could not find contract");
+        let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+
         let vicinity = new_vicinity();
         let backend = new_backend(&vicinity, Default::default());
         let mut evm = Executor::new(12_000_000, &cfg, &backend);
         evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+        evm.setup(addr).unwrap();
 
-        solidity_unit_test(evm, addr, compiled);
+        // reads the address of the greeter from the test contract and its greeting from it
+        let calldata = get_func("function greeting() public view returns (string)")
+            .unwrap()
+            .encode_input(&[])
+            .unwrap();
+        let generated = evm.access_list(Address::zero(), addr, calldata.into()).unwrap();
+        assert_eq!(generated.reason, ExitReason::Succeed(ExitSucceed::Returned));
+        let items = &generated.access_list.0;
+        assert_eq!(items.len(), 2);
+        let test_contract = items.iter().find(|item| item.address == addr).unwrap();
+        assert_eq!(test_contract.storage_keys, vec![H256::zero()]);
+        assert!(items.iter().all(|item| item.storage_keys.len() == 1));
+        assert!(generated.gas_used_with_access_list < generated.gas_used);
+
+        let evm = new_executor();
+        assert!(evm.access_list(Address::zero(), addr, Bytes::default()).is_err());
+    }
+
+    fn reverse(
+        input: &[u8],
+        _: Option<u64>,
+        _: &Context,
+        _: bool,
+    ) -> Result<PrecompileOutput, PrecompileFailure> {
+        let output = input.iter().rev().copied().collect();
+        Ok(PrecompileOutput { exit_status: ExitSucceed::Returned, cost: 0, output, logs: vec![] })
     }
 
     #[test]
-    fn failing_with_no_reason_if_no_setup() {
+    fn registers_precompiles() {
         let cfg = Config::istanbul();
+        let vicinity = new_vicinity();
+        let backend = new_backend(&vicinity, Default::default());
 
-        let compiled = COMPILED.get("GreeterTest").expect("could not find contract");
+        let first = Address::from_low_u64_be(0xff01);
+        let second = Address::from_low_u64_be(0xff02);
+        let mut extra = BTreeMap::new();
+        extra.insert(first, reverse as PrecompileFn);
+        let mut evm = Executor::with_precompiles(12_000_000, &cfg, &backend, extra)
+            .precompile(second, reverse);
+
+        for address in [first, second] {
+            let (output, status, _) = evm
+                .call_raw(Address::zero(), address, vec![1, 2, 3].into(), 0.into(), false)
+                .unwrap();
+            assert_eq!(status, ExitReason::Succeed(ExitSucceed::Returned));
+            assert_eq!(output.as_ref(), &[3, 2, 1]);
+        }
 
+        // copies keep the registered precompiles
+        let mut copy = evm.clone();
+        let (output, _, _) =
+            copy.call_raw(Address::zero(), first, vec![1, 2].into(), 0.into(), false).unwrap();
+        assert_eq!(output.as_ref(), &[2, 1]);
+    }
+
+    /// An ecrecover which ignores the signature and always recovers `0xaa..aa`
+    fn stub_ecrecover(
+        _: &[u8],
+        _: Option<u64>,
+        _: &Context,
+        _: bool,
+    ) -> Result<PrecompileOutput, PrecompileFailure> {
+        let output = H256::from(Address::repeat_byte(0xaa)).as_bytes().to_vec();
+        Ok(PrecompileOutput { exit_status: ExitSucceed::Returned, cost: 0, output, logs: vec![] })
+    }
+
+    #[test]
+    fn stubs_standard_precompiles() {
+        let compiled = COMPILED.get("Greeter").expect("could not find contract");
         let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
 
-        let vicinity = new_vicinity();
+        let mut evm = new_executor().precompile(Address::from_low_u64_be(1), stub_ecrecover);
+        evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+
+        let func = get_func(
+            "function recover(bytes32 hash, uint8 v, bytes32 r, bytes32 s) public pure returns (address)",
+        )
+        .unwrap();
+        let args = (H256::zero(), 27u8, H256::repeat_byte(1), H256::repeat_byte(2));
+        let (signer, _, _) =
+            evm.call::<Address, _>(Address::zero(), addr, &func, args, 0.into()).unwrap();
+        assert_eq!(signer, Address::repeat_byte(0xaa));
+    }
+
+    #[test]
+    fn meters_gas() {
+        let compiled = COMPILED.get("Greeter").expect("could not find contract");
+        let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+        let greet = get_func("function greet(string greeting) external").unwrap();
+
+        let mut evm = new_executor();
+        evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+        evm.call::<(), _>(Address::zero(), addr, &greet, "hi".to_owned(), 0.into()).unwrap();
+
+        // clearing the greeting's slot gets refunded
+        let clear = |mut evm: Executor<'_, _>| {
+            let (_, status, gas) =
+                evm.call::<(), _>(Address::zero(), addr, &greet, String::new(), 0.into()).unwrap();
+            assert!(matches!(status, ExitReason::Succeed(_)));
+            gas
+        };
+        let gross = clear(evm.clone());
+        let refunded = clear(evm.clone().gas_metering(GasMetering::Refunded));
+        assert!(refunded < gross);
+        assert_eq!(clear(evm.clone().gas_metering(GasMetering::Disabled)), 0);
+
+        // without metering, calls do not run out of gas
+        let mut evm = evm.gas_metering(GasMetering::Disabled);
+        evm.set_gas_limit(21_000);
+        let (_, status, _) =
+            evm.call::<(), _>(Address::zero(), addr, &greet, "gm".to_owned(), 0.into()).unwrap();
+        assert!(matches!(status, ExitReason::Succeed(_)));
+    }
+
+    #[test]
+    fn checks_block_env() {
+        let cfg = Config::istanbul();
+        let compiled = COMPILED.get("Greeter").expect("could not find contract");
+        let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+
+        let mut vicinity = new_vicinity();
+        let block = BlockEnv { number: 10, timestamp: 42, ..Default::default() };
+        block.fill_vicinity(&mut vicinity);
         let backend = new_backend(&vicinity, Default::default());
         let mut evm = Executor::new(12_000_000, &cfg, &backend);
         evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
 
+        evm.set_block_env(block).unwrap();
+        let func = get_func("function time() public view returns (uint256)").unwrap();
+        let (time, _, _) = evm.call::<U256, _>(Address::zero(), addr, &func, (), 0.into()).unwrap();
+        assert_eq!(time, 42.into());
+
+        // the block is part of the backend and cannot be changed afterwards
+        assert!(evm.set_block_env(BlockEnv::default()).is_err());
+        assert!(evm.set_block_env(BlockEnv { base_fee: 1.into(), ..block }).is_err());
+    }
+
+    #[test]
+    fn reads_chain_id() {
+        let cfg = Config::istanbul();
+        let compiled = COMPILED.get("Greeter").expect("could not find contract");
+        let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+
+        let vicinity = MemoryVicinity { chain_id: 5.into(), ..new_vicinity() };
+        let backend = new_backend(&vicinity, Default::default());
+        let mut evm = Executor::new(12_000_000, &cfg, &backend);
+        evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+
+        let func = get_func("function chainId() public view returns (uint256)").unwrap();
+        let (id, _, _) = evm.call::<U256, _>(Address::zero(), addr, &func, (), 0.into()).unwrap();
+        assert_eq!(id, 5.into());
+    }
+
+    #[test]
+    fn tracks_nonces() {
+        let compiled = COMPILED.get("Greeter").expect("could not find contract");
+        let from = Address::repeat_byte(1);
+
+        let mut evm = new_executor();
+
+        let (addr, _, _) = evm.deploy(from, compiled.bytecode.clone(), 0.into()).unwrap();
+        assert_eq!(evm.get_nonce(from), 1);
+
+        let func = get_func("function greet(string greeting) external").unwrap();
+        evm.call::<(), _>(from, addr, &func, "hi".to_owned(), 0.into()).unwrap();
+        // reverted calls count as well
+        evm.call_raw(from, addr, vec![0xde, 0xad].into(), 0.into(), false).unwrap();
+        assert_eq!(evm.get_nonce(from), 3);
+
+        let (next, _, _) = evm.deploy(from, compiled.bytecode.clone(), 0.into()).unwrap();
+        assert_eq!(next, ethers::utils::get_contract_address(from, 3));
+
+        evm.set_nonce(from, 10);
+        assert_eq!(evm.get_nonce(from), 10);
+        let (next, _, _) = evm.deploy(from, compiled.bytecode.clone(), 0.into()).unwrap();
+        assert_eq!(next, ethers::utils::get_contract_address(from, 10));
+    }
+
+    #[test]
+    fn can_deploy() {
+        let compiled = COMPILED.get("Greeter").expect("could not find contract");
+        let from = Address::repeat_byte(1);
+
+        let mut evm = new_executor();
+
+        let (addr, status, _) = evm.deploy(from, compiled.bytecode.clone(), 0.into()).unwrap();
+        assert_eq!(status, ExitReason::Succeed(ExitSucceed::Returned));
+        assert_eq!(addr, ethers::utils::get_contract_address(from, 0));
+        assert_eq!(evm.code(addr), compiled.runtime_bytecode);
+
+        // the next deployment goes to the next address
+        let (next, _, _) = evm.deploy(from, compiled.bytecode.clone(), 0.into()).unwrap();
+        assert_eq!(next, ethers::utils::get_contract_address(from, 1));
+    }
+
+    #[test]
+    fn can_deploy2() {
+        let compiled = COMPILED.get("Greeter").expect("could not find contract");
+        let salt = H256::repeat_byte(7);
+
+        let mut evm = new_executor();
+
+        let (addr, status, _) =
+            evm.deploy2(Address::zero(), salt, compiled.bytecode.clone(), 0.into()).unwrap();
+        assert_eq!(status, ExitReason::Succeed(ExitSucceed::Returned));
+        assert_eq!(addr, crate::create2_address(salt, &compiled.bytecode));
+        assert_eq!(evm.code(addr), compiled.runtime_bytecode);
+
+        // the address is taken now
+        let (_, status, _) =
+            evm.deploy2(Address::zero(), salt, compiled.bytecode.clone(), 0.into()).unwrap();
+        assert_eq!(status, ExitReason::Revert(ExitRevert::Reverted));
+    }
+
+    #[test]
+    fn failing_with_no_reason_if_no_setup() {
+        let compiled = COMPILED.get("GreeterTest").expect("could not find contract");
+
+        let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
+
+        let mut evm = new_executor();
+        evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
+
         let (status, res) = evm.executor.transact_call(
             Address::zero(),
             addr,
@@ -206,15 +862,11 @@ mod tests {
 
     #[test]
     fn failing_solidity_unit_test() {
-        let cfg = Config::istanbul();
-
         let compiled = COMPILED.get("GreeterTest").expect("could not find contract");
 
         let addr = "0x1000000000000000000000000000000000000000".parse().unwrap();
 
-        let vicinity = new_vicinity();
-        let backend = new_backend(&vicinity, Default::default());
-        let mut evm = Executor::new(12_000_000, &cfg, &backend);
+        let mut evm = new_executor();
         evm.initialize_contracts(vec![(addr, compiled.runtime_bytecode.clone())]);
 
         // call the setup function to deploy the contracts inside the test
@@ -238,7 +890,7 @@ mod tests {
             vec![],
         );
         assert_eq!(status, ExitReason::Revert(ExitRevert::Reverted));
-        let reason = decode_revert(&res).unwrap();
+        let reason = decode_revert(&res, None).unwrap();
         assert_eq!(reason, "not equal to `hi`");
     }
 }
@@ -0,0 +1,256 @@
+use ethers::{
+    abi::RawLog,
+    types::{H160, H256, U256},
+};
+use sputnik::{
+    backend::{Backend, Basic},
+    executor::{StackState, StackSubstateMetadata},
+    ExitError, Transfer,
+};
+use std::{collections::BTreeMap, time::Instant};
+
+/// Stack state which records what the calls running against it do, as they do it, so that it
+/// does not have to be recovered from the whole state afterwards
+#[derive(Clone, Debug)]
+pub struct JournaledState<S> {
+    state: S,
+    /// The logs emitted since they were last taken. They are only kept here, the inner
+    /// state does not see them.
+    logs: Vec<RawLog>,
+    /// The number of logs when each of the current substates was entered, so that the logs of
+    /// reverted substates can be dropped
+    substates: Vec<usize>,
+    /// The storage slots written to since they were last taken, along with the value each of
+    /// them had before the first write
+    storage_writes: BTreeMap<H160, BTreeMap<H256, H256>>,
+    /// Calls still running past it run out of gas
+    deadline: Option<Instant>,
+    /// The number of times the gas was metered since the clock was last read
+    steps: u32,
+    /// Whether the deadline passed, which is remembered so that the calls which get gas back
+    /// from a failed subcall do not carry on until the clock is read again
+    timed_out: bool,
+}
+
+/// How many times the gas gets metered between two reads of the clock. Reading it for every
+/// opcode would slow the execution down noticeably.
+const STEPS_PER_CLOCK_READ: u32 = 1024;
+
+impl<S> JournaledState<S> {
+    pub fn new(state: S) -> Self {
+        Self {
+            state,
+            logs: Vec::new(),
+            substates: Vec::new(),
+            storage_writes: BTreeMap::new(),
+            deadline: None,
+            steps: 0,
+            timed_out: false,
+        }
+    }
+
+    /// Makes the calls which are still running once the deadline passed run out of gas, so
+    /// that a call which does not halt on its own is interrupted. `None` lifts the deadline.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+        self.steps = 0;
+        self.timed_out = false;
+    }
+
+    /// Checks the clock every [`STEPS_PER_CLOCK_READ`] steps for whether the deadline passed
+    fn is_past_deadline(&mut self) -> bool {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return false,
+        };
+        if !self.timed_out {
+            self.steps += 1;
+            if self.steps >= STEPS_PER_CLOCK_READ {
+                self.steps = 0;
+                self.timed_out = Instant::now() > deadline;
+            }
+        }
+        self.timed_out
+    }
+
+    /// Returns the logs of the calls which were not reverted, in the order they were emitted,
+    /// and forgets them
+    pub fn take_logs(&mut self) -> Vec<RawLog> {
+        std::mem::take(&mut self.logs)
+    }
+
+    /// Returns the storage slots written to, keyed by address, along with the value each of
+    /// them had before, and forgets them. Slots written to by reverted calls are included even
+    /// though their value did not change.
+    pub fn take_storage_writes(&mut self) -> BTreeMap<H160, BTreeMap<H256, H256>> {
+        std::mem::take(&mut self.storage_writes)
+    }
+
+    /// The state the journal is kept for
+    pub fn inner(&self) -> &S {
+        &self.state
+    }
+
+    /// Mutable access to the state, whose changes go unrecorded
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Drops the logs of the substate which is being left
+    fn revert_logs(&mut self) {
+        if let Some(len) = self.substates.pop() {
+            self.logs.truncate(len);
+        }
+    }
+}
+
+impl<S: Backend> Backend for JournaledState<S> {
+    fn gas_price(&self) -> U256 {
+        self.state.gas_price()
+    }
+
+    fn origin(&self) -> H160 {
+        self.state.origin()
+    }
+
+    fn block_hash(&self, number: U256) -> H256 {
+        self.state.block_hash(number)
+    }
+
+    fn block_number(&self) -> U256 {
+        self.state.block_number()
+    }
+
+    fn block_coinbase(&self) -> H160 {
+        self.state.block_coinbase()
+    }
+
+    fn block_timestamp(&self) -> U256 {
+        self.state.block_timestamp()
+    }
+
+    fn block_difficulty(&self) -> U256 {
+        self.state.block_difficulty()
+    }
+
+    fn block_gas_limit(&self) -> U256 {
+        self.state.block_gas_limit()
+    }
+
+    fn chain_id(&self) -> U256 {
+        self.state.chain_id()
+    }
+
+    fn exists(&self, address: H160) -> bool {
+        self.state.exists(address)
+    }
+
+    fn basic(&self, address: H160) -> Basic {
+        self.state.basic(address)
+    }
+
+    fn code(&self, address: H160) -> Vec<u8> {
+        self.state.code(address)
+    }
+
+    fn storage(&self, address: H160, index: H256) -> H256 {
+        self.state.storage(address, index)
+    }
+
+    fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
+        self.state.original_storage(address, index)
+    }
+}
+
+impl<'config, S: StackState<'config>> StackState<'config> for JournaledState<S> {
+    fn metadata(&self) -> &StackSubstateMetadata<'config> {
+        self.state.metadata()
+    }
+
+    fn metadata_mut(&mut self) -> &mut StackSubstateMetadata<'config> {
+        // the executor meters the gas of every opcode through here
+        let timed_out = self.is_past_deadline();
+        let metadata = self.state.metadata_mut();
+        if timed_out {
+            // using up the gas left makes the call fail at its next opcode, and the calls
+            // above it at theirs
+            let gas = metadata.gasometer().gas();
+            let _ = metadata.gasometer_mut().record_cost(gas);
+        }
+        metadata
+    }
+
+    fn enter(&mut self, gas_limit: u64, is_static: bool) {
+        self.substates.push(self.logs.len());
+        self.state.enter(gas_limit, is_static)
+    }
+
+    fn exit_commit(&mut self) -> Result<(), ExitError> {
+        self.substates.pop();
+        self.state.exit_commit()
+    }
+
+    fn exit_revert(&mut self) -> Result<(), ExitError> {
+        self.revert_logs();
+        self.state.exit_revert()
+    }
+
+    fn exit_discard(&mut self) -> Result<(), ExitError> {
+        self.revert_logs();
+        self.state.exit_discard()
+    }
+
+    fn is_empty(&self, address: H160) -> bool {
+        self.state.is_empty(address)
+    }
+
+    fn deleted(&self, address: H160) -> bool {
+        self.state.deleted(address)
+    }
+
+    fn is_cold(&self, address: H160) -> bool {
+        self.state.is_cold(address)
+    }
+
+    fn is_storage_cold(&self, address: H160, key: H256) -> bool {
+        self.state.is_storage_cold(address, key)
+    }
+
+    fn inc_nonce(&mut self, address: H160) {
+        self.state.inc_nonce(address)
+    }
+
+    fn set_storage(&mut self, address: H160, key: H256, value: H256) {
+        let original = self.state.storage(address, key);
+        self.storage_writes.entry(address).or_default().entry(key).or_insert(original);
+        self.state.set_storage(address, key, value)
+    }
+
+    fn reset_storage(&mut self, address: H160) {
+        self.state.reset_storage(address)
+    }
+
+    fn log(&mut self, _address: H160, topics: Vec<H256>, data: Vec<u8>) {
+        self.logs.push(RawLog { topics, data });
+    }
+
+    fn set_deleted(&mut self, address: H160) {
+        self.state.set_deleted(address)
+    }
+
+    fn set_code(&mut self, address: H160, code: Vec<u8>) {
+        self.state.set_code(address, code)
+    }
+
+    fn transfer(&mut self, transfer: Transfer) -> Result<(), ExitError> {
+        self.state.transfer(transfer)
+    }
+
+    fn reset_balance(&mut self, address: H160) {
+        self.state.reset_balance(address)
+    }
+
+    fn touch(&mut self, address: H160) {
+        self.state.touch(address)
+    }
+}
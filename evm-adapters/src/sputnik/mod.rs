@@ -4,8 +4,40 @@ pub use evm::*;
 mod forked_backend;
 pub use forked_backend::ForkMemoryBackend;
 
+mod journal;
+pub use journal::JournaledState;
+
+pub mod console;
+
+use crate::{error::Result, BlockEnv, EvmError, EvmVersion};
 use ethers::providers::Middleware;
-use sputnik::backend::MemoryVicinity;
+use sputnik::{backend::MemoryVicinity, Config};
+
+impl EvmVersion {
+    /// The Sputnik config of the hard fork. London is not supported yet.
+    pub fn sputnik_cfg(&self) -> Result<Config> {
+        Ok(match self {
+            EvmVersion::Frontier => Config::frontier(),
+            EvmVersion::Istanbul => Config::istanbul(),
+            EvmVersion::Berlin => Config::berlin(),
+            EvmVersion::London => {
+                return Err(EvmError::Unsupported("the London hard fork".to_owned()))
+            }
+        })
+    }
+}
+
+impl BlockEnv {
+    /// Sets the block of the vicinity, which Sputnik reads it from. The base fee is ignored
+    /// since Sputnik does not support London yet.
+    pub fn fill_vicinity(&self, vicinity: &mut MemoryVicinity) {
+        vicinity.block_number = self.number.into();
+        vicinity.block_timestamp = self.timestamp.into();
+        vicinity.block_coinbase = self.coinbase;
+        vicinity.block_difficulty = self.difficulty;
+        vicinity.block_gas_limit = self.gas_limit.into();
+    }
+}
 
 pub async fn vicinity<M: Middleware>(
     provider: &M,
@@ -12,6 +12,16 @@ contract Greeter {
         return block.timestamp;
     }
 
+    function chainId() public view returns (uint256 id) {
+        assembly {
+            id := chainid()
+        }
+    }
+
+    function recover(bytes32 hash, uint8 v, bytes32 r, bytes32 s) public pure returns (address) {
+        return ecrecover(hash, v, r, s);
+    }
+
     function gm() public {
         greeting = "gm";
     }
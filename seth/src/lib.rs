@@ -276,8 +276,56 @@ where
     pub async fn gas_price(&self) -> Result<U256> {
         Ok(self.provider.get_gas_price().await?)
     }
+
+    /// Lists the populated storage slots of a contract, without knowing its storage layout.
+    ///
+    /// The first `slots` sequential slots are scanned (that's where Solidity places the state
+    /// variables), along with the well-known EIP-1967 proxy slots. Only non-zero slots are
+    /// returned.
+    ///
+    /// ```no_run
+    /// use seth::Seth;
+    /// use ethers_core::types::Address;
+    /// use ethers_providers::{Provider, Http};
+    /// use std::{str::FromStr, convert::TryFrom};
+    ///
+    /// # async fn foo() -> eyre::Result<()> {
+    /// let provider = Provider::<Http>::try_from("http://localhost:8545")?;
+    /// let seth = Seth::new(provider);
+    /// let address = Address::from_str("0xB3C95ff08316fb2F2e3E52Ee82F8e7b605Aa1304")?;
+    /// for (slot, value) in seth.storage_explore(address, 10, None).await? {
+    ///     println!("{:?} {:?}", slot, value);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn storage_explore<T: Into<NameOrAddress> + Clone + Send + Sync>(
+        &self,
+        address: T,
+        slots: u64,
+        block: Option<BlockId>,
+    ) -> Result<Vec<(H256, H256)>> {
+        let sequential = (0..slots).map(H256::from_low_u64_be);
+        let well_known = EIP1967_SLOTS.iter().map(|slot| H256::from_str(slot).unwrap());
+
+        let mut populated = Vec::new();
+        for slot in sequential.chain(well_known) {
+            let value = self.provider.get_storage_at(address.clone(), slot, block).await?;
+            if !value.is_zero() {
+                populated.push((slot, value));
+            }
+        }
+        Ok(populated)
+    }
 }
 
+/// The EIP-1967 implementation, admin and beacon slots used by proxies
+const EIP1967_SLOTS: [&str; 3] = [
+    "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc",
+    "0xb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103",
+    "0xa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50",
+];
+
 pub struct SimpleSeth;
 impl SimpleSeth {
     /// Converts UTF-8 text input to hex
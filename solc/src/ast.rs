@@ -0,0 +1,330 @@
+//! Helpers over the compact JSON AST which solc emits, see
+//! [`SolcBuilder::build_asts`](crate::SolcBuilder::build_asts)
+
+use eyre::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    str::FromStr,
+};
+
+/// Calls `f` on every AST node (i.e. every JSON object with a `nodeType`) below and including
+/// `node`, in depth-first order
+pub fn walk<'a>(node: &'a Value, f: &mut impl FnMut(&'a Value)) {
+    match node {
+        Value::Object(map) => {
+            if map.contains_key("nodeType") {
+                f(node);
+            }
+            map.values().for_each(|child| walk(child, f));
+        }
+        Value::Array(items) => items.iter().for_each(|child| walk(child, f)),
+        _ => {}
+    }
+}
+
+/// Returns the `nodeType` of an AST node
+pub fn node_type(node: &Value) -> Option<&str> {
+    node.get("nodeType").and_then(Value::as_str)
+}
+
+/// Selects AST nodes by type and attributes, similar to CSS selectors. A selector is a
+/// whitespace separated list of steps, each matching the descendants of the nodes matched by
+/// the previous step, e.g.
+/// `ContractDefinition[name=Greeter] FunctionDefinition[visibility=public]`.
+///
+/// A step is a `nodeType` (or `*` for any node), followed by any number of `[attribute=value]`
+/// filters on the node's top-level attributes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Step {
+    node_type: Option<String>,
+    filters: Vec<(String, String)>,
+}
+
+impl Step {
+    fn matches(&self, node: &Value) -> bool {
+        if let Some(node_type) = &self.node_type {
+            if self::node_type(node) != Some(node_type.as_str()) {
+                return false
+            }
+        }
+        self.filters.iter().all(|(attribute, expected)| match node.get(attribute) {
+            Some(Value::String(value)) => value == expected,
+            Some(value) => value.to_string() == *expected,
+            None => false,
+        })
+    }
+}
+
+impl FromStr for Selector {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let steps = s.split_whitespace().map(|step| -> Result<Step> {
+            let (node_type, mut filters) =
+                step.split_at(step.find('[').unwrap_or_else(|| step.len()));
+            let node_type = match node_type {
+                "" => eyre::bail!("missing node type in `{}`, use `*` to match any node", step),
+                "*" => None,
+                node_type => Some(node_type.to_owned()),
+            };
+
+            let mut step = Step { node_type, filters: Vec::new() };
+            while !filters.is_empty() {
+                let (filter, rest) = filters
+                    .strip_prefix('[')
+                    .and_then(|filters| filters.split_once(']'))
+                    .ok_or_else(|| eyre::eyre!("invalid filter `{}`", filters))?;
+                let (attribute, value) = filter.split_once('=').ok_or_else(|| {
+                    eyre::eyre!("filter `{}` is not of the form `attribute=value`", filter)
+                })?;
+                step.filters.push((attribute.to_owned(), value.to_owned()));
+                filters = rest;
+            }
+            Ok(step)
+        });
+        let steps = steps.collect::<Result<Vec<_>>>()?;
+        if steps.is_empty() {
+            eyre::bail!("empty selector")
+        }
+        Ok(Self { steps })
+    }
+}
+
+impl Selector {
+    /// Returns the nodes below and including `ast` which match the selector, in depth-first
+    /// order and without duplicates
+    pub fn query<'a>(&self, ast: &'a Value) -> Vec<&'a Value> {
+        let mut matches = vec![ast];
+        for (i, step) in self.steps.iter().enumerate() {
+            let mut next: Vec<&Value> = Vec::new();
+            for node in matches {
+                let mut visit = |candidate: &'a Value| {
+                    let candidate_matches = step.matches(candidate) &&
+                        !next.iter().any(|found| std::ptr::eq(*found, candidate));
+                    if candidate_matches {
+                        next.push(candidate)
+                    }
+                };
+                if i == 0 {
+                    walk(node, &mut visit);
+                } else {
+                    // later steps only match strict descendants
+                    node.as_object()
+                        .into_iter()
+                        .flat_map(|map| map.values())
+                        .for_each(|child| walk(child, &mut visit));
+                }
+            }
+            matches = next;
+        }
+        matches
+    }
+}
+
+/// Returns the nodes of the AST matching the selector, see [`Selector`]
+pub fn query<'a>(ast: &'a Value, selector: &str) -> Result<Vec<&'a Value>> {
+    Ok(selector.parse::<Selector>()?.query(ast))
+}
+
+/// How a function gets called
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallKind {
+    /// A jump inside the calling contract, e.g. `foo()` or `super.foo()`
+    Internal,
+    /// A message call, e.g. `token.transfer(...)` or `this.foo()`
+    External,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Call {
+    pub from: String,
+    pub to: String,
+    pub kind: CallKind,
+}
+
+/// Static call graph of the compiled functions. Functions are named `Contract.function`,
+/// with `constructor`, `fallback` and `receive` for the special functions.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CallGraph {
+    pub functions: BTreeSet<String>,
+    pub calls: BTreeSet<Call>,
+}
+
+impl CallGraph {
+    /// Builds the call graph from the ASTs of all the sources, so that calls across files
+    /// get resolved
+    pub fn new(asts: &BTreeMap<String, Value>) -> Self {
+        // solc assigns ids which are unique across all the sources of a compilation
+        let mut names = HashMap::new();
+        let mut functions = Vec::new();
+        for ast in asts.values() {
+            walk(ast, &mut |node| {
+                if node_type(node) != Some("ContractDefinition") {
+                    return
+                }
+                let contract = node["name"].as_str().unwrap_or_default();
+                let members = node["nodes"].as_array().map(Vec::as_slice).unwrap_or_default();
+                for function in members {
+                    if node_type(function) != Some("FunctionDefinition") {
+                        continue
+                    }
+                    let name = match function["kind"].as_str() {
+                        Some("function") | None => function["name"].as_str().unwrap_or_default(),
+                        Some(kind) => kind,
+                    };
+                    let name = format!("{}.{}", contract, name);
+                    if let Some(id) = function["id"].as_i64() {
+                        names.insert(id, name.clone());
+                    }
+                    functions.push((name, function));
+                }
+            });
+        }
+
+        let mut graph = Self::default();
+        for (name, function) in functions {
+            walk(&function["body"], &mut |node| {
+                if node_type(node) != Some("FunctionCall") {
+                    return
+                }
+                let callee = &node["expression"];
+                let kind = match node_type(callee) {
+                    Some("Identifier") => CallKind::Internal,
+                    Some("MemberAccess") if is_super(&callee["expression"]) => CallKind::Internal,
+                    Some("MemberAccess") => CallKind::External,
+                    _ => return,
+                };
+                // events, builtins and type conversions do not resolve to a function
+                let to =
+                    match callee["referencedDeclaration"].as_i64().and_then(|id| names.get(&id)) {
+                        Some(to) => to.clone(),
+                        None => return,
+                    };
+                graph.calls.insert(Call { from: name.clone(), to, kind });
+            });
+            graph.functions.insert(name);
+        }
+        graph
+    }
+
+    /// Renders the graph in the Graphviz DOT format, with external calls drawn dashed
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph {\n");
+        for function in &self.functions {
+            out.push_str(&format!("  {:?};\n", function));
+        }
+        for call in &self.calls {
+            let style = match call.kind {
+                CallKind::Internal => "",
+                CallKind::External => " [style=dashed]",
+            };
+            out.push_str(&format!("  {:?} -> {:?}{};\n", call.from, call.to, style));
+        }
+        out.push('}');
+        out
+    }
+}
+
+fn is_super(node: &Value) -> bool {
+    node_type(node) == Some("Identifier") && node["name"] == "super"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(expression: Value) -> Value {
+        json!({ "nodeType": "ExpressionStatement", "expression": {
+            "nodeType": "FunctionCall", "expression": expression, "arguments": []
+        }})
+    }
+
+    fn function(id: i64, name: &str, statements: Vec<Value>) -> Value {
+        json!({
+            "nodeType": "FunctionDefinition", "id": id, "name": name, "kind": "function",
+            "body": { "nodeType": "Block", "statements": statements }
+        })
+    }
+
+    #[test]
+    fn queries_nodes() {
+        let ast = json!({ "nodeType": "SourceUnit", "nodes": [
+            { "nodeType": "ContractDefinition", "name": "Greeter", "nodes": [
+                function(1, "greet", vec![]),
+                function(2, "gm", vec![]),
+            ]},
+            { "nodeType": "ContractDefinition", "name": "Other", "nodes": [
+                function(3, "greet", vec![]),
+            ]},
+        ]});
+
+        let ids = |selector: &str| {
+            query(&ast, selector)
+                .unwrap()
+                .into_iter()
+                .map(|node| node["id"].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids("FunctionDefinition"), vec![json!(1), json!(2), json!(3)]);
+        assert_eq!(ids("FunctionDefinition[name=greet]"), vec![json!(1), json!(3)]);
+        assert_eq!(
+            ids("ContractDefinition[name=Greeter] FunctionDefinition"),
+            vec![json!(1), json!(2)]
+        );
+        assert_eq!(ids("* FunctionDefinition[name=gm][id=2]"), vec![json!(2)]);
+        assert!(ids("ContractDefinition[name=Greeter] ContractDefinition").is_empty());
+
+        assert!(query(&ast, "").is_err());
+        assert!(query(&ast, "[name=greet]").is_err());
+        assert!(query(&ast, "FunctionDefinition[name]").is_err());
+    }
+
+    #[test]
+    fn builds_call_graph() {
+        let token = json!({ "nodeType": "ContractDefinition", "name": "Token", "nodes": [
+            function(1, "transfer", vec![call(json!({
+                "nodeType": "Identifier", "name": "_move", "referencedDeclaration": 2
+            }))]),
+            function(2, "_move", vec![call(json!({
+                "nodeType": "Identifier", "name": "require", "referencedDeclaration": -18
+            }))]),
+        ]});
+        let user = json!({ "nodeType": "ContractDefinition", "name": "User", "nodes": [
+            function(3, "pay", vec![call(json!({
+                "nodeType": "MemberAccess", "memberName": "transfer", "referencedDeclaration": 1,
+                "expression": { "nodeType": "Identifier", "name": "token" }
+            }))]),
+        ]});
+        let mut asts = BTreeMap::new();
+        asts.insert("Token.sol".to_owned(), json!({ "nodeType": "SourceUnit", "nodes": [token] }));
+        asts.insert("User.sol".to_owned(), json!({ "nodeType": "SourceUnit", "nodes": [user] }));
+
+        let graph = CallGraph::new(&asts);
+        assert_eq!(graph.functions.len(), 3);
+        assert_eq!(
+            graph.calls.iter().cloned().collect::<Vec<_>>(),
+            vec![
+                Call {
+                    from: "Token.transfer".into(),
+                    to: "Token._move".into(),
+                    kind: CallKind::Internal
+                },
+                Call {
+                    from: "User.pay".into(),
+                    to: "Token.transfer".into(),
+                    kind: CallKind::External
+                },
+            ]
+        );
+        assert!(graph.to_dot().contains(r#"  "User.pay" -> "Token.transfer" [style=dashed];"#));
+    }
+}
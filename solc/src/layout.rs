@@ -0,0 +1,95 @@
+//! The `storageLayout` compiler output, see
+//! [`SolcBuilder::build_storage_layouts`](crate::SolcBuilder::build_storage_layouts)
+
+use ethers::types::U256;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Where each state variable of a contract is stored
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageLayout {
+    pub storage: Vec<StorageSlot>,
+    /// The types of the state variables, keyed by the type identifier
+    #[serde(default)]
+    pub types: BTreeMap<String, StorageType>,
+}
+
+impl StorageLayout {
+    /// Returns the storage location of the state variable with the provided name. Inherited
+    /// variables are included.
+    pub fn variable(&self, label: &str) -> Option<&StorageSlot> {
+        self.storage.iter().find(|slot| slot.label == label)
+    }
+
+    /// Returns the human readable name of a variable's type, e.g. `mapping(address => uint256)`
+    pub fn type_label<'a>(&'a self, slot: &'a StorageSlot) -> &'a str {
+        self.types.get(&slot.ty).map(|ty| ty.label.as_str()).unwrap_or(&slot.ty)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageSlot {
+    /// The name of the state variable
+    pub label: String,
+    /// The slot in decimal, see [`index`](Self::index)
+    pub slot: String,
+    /// The offset in bytes within the slot, for variables packed together
+    pub offset: u64,
+    /// The type identifier, which [`StorageLayout::types`] is keyed by
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The contract declaring the variable, as `path:name`
+    pub contract: String,
+    pub ast_id: u64,
+}
+
+impl StorageSlot {
+    /// Parses the slot of the variable
+    pub fn index(&self) -> Result<U256> {
+        U256::from_dec_str(&self.slot)
+            .map_err(|err| eyre::eyre!("invalid slot {}: {}", self.slot, err))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageType {
+    /// `inplace`, `mapping`, `dynamic_array` or `bytes`
+    pub encoding: String,
+    pub label: String,
+    /// The size of the type in bytes, in decimal
+    pub number_of_bytes: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_storage_layout() {
+        let layout = r#"{
+            "storage": [
+                { "astId": 3, "contract": "Token.sol:Token", "label": "owner", "offset": 0, "slot": "0", "type": "t_address" },
+                { "astId": 5, "contract": "Token.sol:Token", "label": "paused", "offset": 20, "slot": "0", "type": "t_bool" },
+                { "astId": 9, "contract": "Token.sol:Token", "label": "balances", "offset": 0, "slot": "1", "type": "t_mapping(t_address,t_uint256)" }
+            ],
+            "types": {
+                "t_address": { "encoding": "inplace", "label": "address", "numberOfBytes": "20" },
+                "t_bool": { "encoding": "inplace", "label": "bool", "numberOfBytes": "1" },
+                "t_mapping(t_address,t_uint256)": { "encoding": "mapping", "key": "t_address", "label": "mapping(address => uint256)", "numberOfBytes": "32", "value": "t_uint256" }
+            }
+        }"#;
+        let layout: StorageLayout = serde_json::from_str(layout).unwrap();
+
+        let paused = layout.variable("paused").unwrap();
+        assert_eq!(paused.index().unwrap(), 0.into());
+        assert_eq!(paused.offset, 20);
+
+        let balances = layout.variable("balances").unwrap();
+        assert_eq!(balances.index().unwrap(), 1.into());
+        assert_eq!(layout.type_label(balances), "mapping(address => uint256)");
+        assert!(layout.variable("supply").is_none());
+    }
+}
@@ -1,14 +1,24 @@
-use ethers::core::utils::{CompiledContract, Solc};
+use ethers::core::utils::CompiledContract;
 use eyre::Result;
 use semver::{Version, VersionReq};
+use serde_json::Value;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::File,
     io::{BufRead, BufReader},
     path::{Path, PathBuf},
+    process::Command,
     time::Instant,
 };
 
+pub mod ast;
+pub mod patch;
+
+mod layout;
+mod link;
+pub use layout::{StorageLayout, StorageSlot, StorageType};
+pub use link::{link, LinkReference, LinkReferences};
+
 #[cfg(test)]
 use std::sync::Mutex;
 #[cfg(test)]
@@ -24,6 +34,40 @@ pub struct SolcBuilder<'a> {
     lib_paths: &'a [String],
     versions: Vec<Version>,
     releases: Vec<Version>,
+    /// Mapping of contract name to the path of the file it was declared in
+    sources: HashMap<String, String>,
+    /// The library placeholders of the compiled contracts which have any, by contract name
+    link_references: HashMap<String, LinkReferences>,
+    /// The EVM version to target, e.g. `istanbul`. Defaults to the compiler's default.
+    evm_version: Option<&'a str>,
+}
+
+/// The contracts compiled by one compiler invocation, the files declaring them and the library
+/// placeholders of the ones which have any, all keyed by contract name
+type BuildOutput =
+    (HashMap<String, CompiledContract>, HashMap<String, String>, HashMap<String, LinkReferences>);
+
+/// The EVM versions solc can target, along with the first release supporting them
+const EVM_VERSIONS: &[(&str, Version)] = &[
+    ("homestead", Version::new(0, 4, 21)),
+    ("tangerineWhistle", Version::new(0, 4, 21)),
+    ("spuriousDragon", Version::new(0, 4, 21)),
+    ("byzantium", Version::new(0, 4, 21)),
+    ("constantinople", Version::new(0, 4, 21)),
+    ("petersburg", Version::new(0, 5, 5)),
+    ("istanbul", Version::new(0, 5, 14)),
+    ("berlin", Version::new(0, 8, 5)),
+    ("london", Version::new(0, 8, 7)),
+];
+
+/// Whether the compiler release can target the EVM version. Unknown EVM versions are left
+/// for the compiler to reject.
+fn supports_evm_version(version: &Version, evm_version: &str) -> bool {
+    EVM_VERSIONS
+        .iter()
+        .find(|(name, _)| *name == evm_version)
+        .map(|(_, since)| version >= since)
+        .unwrap_or(true)
 }
 
 impl<'a> SolcBuilder<'a> {
@@ -41,43 +85,180 @@ impl<'a> SolcBuilder<'a> {
                 Vec::new()
             }
         };
-        Ok(Self { contracts, remappings, lib_paths, versions, releases })
+        Ok(Self {
+            contracts,
+            remappings,
+            lib_paths,
+            versions,
+            releases,
+            sources: HashMap::new(),
+            link_references: HashMap::new(),
+            evm_version: None,
+        })
+    }
+
+    /// Sets the EVM version to compile for, e.g. `istanbul`. Compilers which predate it
+    /// target their default EVM version instead, which is the latest one they know.
+    pub fn evm_version(mut self, evm_version: &'a str) -> Self {
+        self.evm_version = Some(evm_version);
+        self
     }
 
-    /// Builds all provided contract files with the specified compiler version.
+    /// Builds all provided contract files with the specified compiler version, returning the
+    /// compiled contracts along with the files declaring them and their library placeholders.
     /// Assumes that the lib-paths and remappings have already been specified and
     /// that the correct compiler version is provided.
     // FIXME: Does NOT support contracts with the same name.
     #[tracing::instrument(skip(self, files))]
-    fn build(
-        &self,
-        version: &str,
-        files: Vec<String>,
-    ) -> Result<HashMap<String, CompiledContract>> {
-        let compiler_path = find_installed_version_path(version)?
-            .ok_or_else(|| eyre::eyre!("version {} not installed", version))?;
-
+    fn build(&self, version: &str, files: Vec<String>) -> Result<BuildOutput> {
         // tracing::trace!(?files);
-        let mut solc = Solc::new_with_paths(files).solc_path(compiler_path);
+        // `Solc` cannot decode bytecode with library placeholders, so we parse the output
+        // ourselves
+        let output = self.combined_json(version, &files, "abi,bin,bin-runtime")?;
+        let output = output
+            .get("contracts")
+            .and_then(Value::as_object)
+            .ok_or_else(|| eyre::eyre!("solc output has no contracts"))?;
+        let ids = output.keys().map(String::as_str).collect::<Vec<_>>();
+
+        let mut contracts = HashMap::new();
+        let mut sources = HashMap::new();
+        let mut link_references = HashMap::new();
+        for (id, contract) in output {
+            let abi = match &contract["abi"] {
+                // older compilers encode the nested outputs as JSON strings
+                Value::String(abi) => serde_json::from_str(abi)?,
+                abi => serde_json::from_value(abi.clone())?,
+            };
+            let bin = contract["bin"].as_str().unwrap_or_default();
+            let (bytecode, bytecode_references) = link::decode_unlinked(bin, &ids)?;
+            let bin_runtime = contract["bin-runtime"].as_str().unwrap_or_default();
+            let (runtime_bytecode, runtime_references) = link::decode_unlinked(bin_runtime, &ids)?;
+
+            // contracts are keyed by `path:name`
+            let (path, name) = id.rsplit_once(':').unwrap_or(("", id));
+            let name = name.to_owned();
+            // contracts imported from outside the compiled files (e.g. libraries) are omitted
+            if files.iter().any(|file| file == path) {
+                sources.insert(name.clone(), path.to_owned());
+            }
+            if !bytecode_references.is_empty() || !runtime_references.is_empty() {
+                let references = LinkReferences {
+                    bytecode: bytecode_references,
+                    runtime_bytecode: runtime_references,
+                };
+                link_references.insert(name.clone(), references);
+            }
+            let contract = CompiledContract {
+                abi,
+                bytecode: bytecode.into(),
+                runtime_bytecode: runtime_bytecode.into(),
+            };
+            contracts.insert(name, contract);
+        }
+        Ok((contracts, sources, link_references))
+    }
+
+    /// The lib paths, remappings and EVM version arguments which every invocation of the
+    /// compiler version gets
+    fn solc_args(&self, version: &str) -> Result<Vec<String>> {
         let lib_paths = self
             .lib_paths
             .iter()
             .filter(|path| PathBuf::from(path).exists())
-            .map(|path| {
-                std::fs::canonicalize(path).unwrap().into_os_string().into_string().unwrap()
-            })
-            .collect::<Vec<_>>()
+            .map(canonicalize)
+            .collect::<Result<Vec<_>>>()?
             .join(",");
 
         // tracing::trace!(?lib_paths);
-        solc = solc.args(std::array::IntoIter::new(["--allow-paths", &lib_paths]));
+        let mut args = vec!["--allow-paths".to_owned(), lib_paths];
 
         // tracing::trace!(?self.remappings);
-        if !self.remappings.is_empty() {
-            solc = solc.args(self.remappings)
+        args.extend(self.remappings.iter().map(|remapping| normalize_separators(remapping)));
+
+        if let Some(evm_version) = self.evm_version {
+            if supports_evm_version(&Version::parse(version)?, evm_version) {
+                args.extend(vec!["--evm-version".to_owned(), evm_version.to_owned()]);
+            } else {
+                tracing::warn!(
+                    "solc {} does not support the {} EVM, compiling for its default instead",
+                    version,
+                    evm_version
+                );
+            }
+        }
+        Ok(args)
+    }
+
+    /// Runs the compiler directly, requesting the provided `--combined-json` outputs (e.g.
+    /// `ast` or `storage-layout`) which `ethers::utils::Solc` does not expose
+    #[tracing::instrument(skip(self, files))]
+    fn combined_json(&self, version: &str, files: &[String], outputs: &str) -> Result<Value> {
+        let compiler_path = find_installed_version_path(version)?
+            .ok_or_else(|| eyre::eyre!("version {} not installed", version))?;
+
+        let output = Command::new(compiler_path)
+            .arg("--combined-json")
+            .arg(outputs)
+            .args(self.solc_args(version)?)
+            .args(files)
+            .output()?;
+        if !output.status.success() {
+            eyre::bail!("{}", String::from_utf8_lossy(&output.stderr))
+        }
+
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    /// Builds the compact JSON ASTs of all contract files, keyed by source path. Imported
+    /// files are included as well.
+    #[tracing::instrument(skip(self))]
+    pub fn build_asts(&mut self) -> Result<BTreeMap<String, Value>> {
+        let mut asts = BTreeMap::new();
+        for (version, files) in self.contract_versions()? {
+            // the compact AST only became the default in 0.8.0
+            let outputs = if Version::parse(&version)? < Version::new(0, 8, 0) {
+                "ast,compact-format"
+            } else {
+                "ast"
+            };
+            let output = self.combined_json(&version, &files, outputs)?;
+            let sources = output
+                .get("sources")
+                .and_then(Value::as_object)
+                .ok_or_else(|| eyre::eyre!("solc output has no sources"))?;
+            for (path, source) in sources {
+                if let Some(ast) = source.get("AST") {
+                    asts.insert(path.clone(), ast.clone());
+                }
+            }
         }
+        Ok(asts)
+    }
 
-        Ok(solc.build()?)
+    /// Builds the storage layouts of all contracts, keyed by contract name
+    #[tracing::instrument(skip(self))]
+    pub fn build_storage_layouts(&mut self) -> Result<HashMap<String, StorageLayout>> {
+        let mut layouts = HashMap::new();
+        for (version, files) in self.contract_versions()? {
+            let output = self.combined_json(&version, &files, "storage-layout")?;
+            let contracts = output
+                .get("contracts")
+                .and_then(Value::as_object)
+                .ok_or_else(|| eyre::eyre!("solc output has no contracts"))?;
+            for (id, contract) in contracts {
+                let layout = match &contract["storage-layout"] {
+                    // older compilers encode the nested outputs as JSON strings
+                    Value::String(layout) => serde_json::from_str(layout)?,
+                    Value::Null => continue,
+                    layout => serde_json::from_value(layout.clone())?,
+                };
+                // contracts are keyed by `path:name`
+                let name = id.rsplit_once(':').map(|(_, name)| name).unwrap_or(id);
+                layouts.insert(name.to_owned(), layout);
+            }
+        }
+        Ok(layouts)
     }
 
     /// Builds all contracts with their corresponding compiler versions
@@ -86,17 +267,37 @@ impl<'a> SolcBuilder<'a> {
         let contracts_by_version = self.contract_versions()?;
         let start = Instant::now();
         let res = contracts_by_version.into_iter().try_fold(
-            HashMap::new(),
-            |mut map, (version, files)| {
-                let res = self.build(&version, files)?;
-                map.extend(res);
-                Ok::<_, eyre::Error>(map)
+            (HashMap::new(), HashMap::new(), HashMap::new()),
+            |(mut map, mut sources, mut link_references), (version, files)| {
+                let (contracts, paths, references) = self.build(&version, files)?;
+                map.extend(contracts);
+                sources.extend(paths);
+                link_references.extend(references);
+                Ok::<_, eyre::Error>((map, sources, link_references))
             },
         );
         let duration = Instant::now().duration_since(start);
         tracing::info!(compilation_time = ?duration);
 
-        res
+        let (contracts, sources, link_references) = res?;
+        self.sources = sources;
+        self.link_references = link_references;
+
+        Ok(contracts)
+    }
+
+    /// Returns the library placeholders of the contracts compiled in the last call to
+    /// [`build_all`](Self::build_all), keyed by contract name. Contracts which do not use any
+    /// libraries are omitted. The placeholders are zeroed in the compiled bytecode and have
+    /// to be filled in with [`link`] before deploying it.
+    pub fn link_references(&self) -> &HashMap<String, LinkReferences> {
+        &self.link_references
+    }
+
+    /// Returns the mapping of contract name to the path of the file it was declared in,
+    /// for the contracts compiled in the last call to [`build_all`](Self::build_all)
+    pub fn sources(&self) -> &HashMap<String, String> {
+        &self.sources
     }
 
     /// Given a Solidity file, it detects the latest compiler version which can be used
@@ -104,15 +305,10 @@ impl<'a> SolcBuilder<'a> {
     /// compiler version is not installed, it also proceeds to install it.
     #[tracing::instrument(err)]
     fn detect_version(&mut self, fname: &Path) -> Result<Option<(Version, String)>> {
-        let path = std::fs::canonicalize(fname)?;
+        let path_str = canonicalize(fname)?;
 
         // detects the required solc version
-        let sol_version = Self::version_req(&path)?;
-
-        let path_str = path
-            .into_os_string()
-            .into_string()
-            .map_err(|_| eyre::eyre!("invalid path, maybe not utf-8?"))?;
+        let sol_version = Self::version_req(Path::new(&path_str))?;
 
         #[cfg(test)]
         // take the lock in tests, we use this to enforce that
@@ -218,6 +414,28 @@ impl<'a> SolcBuilder<'a> {
     }
 }
 
+/// Canonicalizes the path into the form which gets passed to solc and used as the source
+/// path of the compiled contracts, i.e. absolute, without the `\\?\` prefix that Windows
+/// adds to canonicalized paths and with `/` as the separator.
+pub fn canonicalize(path: impl AsRef<Path>) -> Result<String> {
+    let path = std::fs::canonicalize(path)?
+        .into_os_string()
+        .into_string()
+        .map_err(|_| eyre::eyre!("invalid path, maybe not utf-8?"))?;
+    let path = path.strip_prefix(r"\\?\").unwrap_or(&path);
+    Ok(normalize_separators(path))
+}
+
+/// Replaces the platform's path separators with `/`, which solc understands on all
+/// platforms. This is a no-op on Unix.
+pub fn normalize_separators(path: &str) -> String {
+    if std::path::MAIN_SEPARATOR == '/' {
+        path.to_owned()
+    } else {
+        path.replace(std::path::MAIN_SEPARATOR, "/")
+    }
+}
+
 /// Returns the path for an installed version
 fn find_installed_version_path(version: &str) -> Result<Option<PathBuf>> {
     let home_dir = svm::SVM_HOME.clone();
@@ -227,7 +445,7 @@ fn find_installed_version_path(version: &str) -> Result<Option<PathBuf>> {
         .map(|version_dir| version_dir.path())
         .find(|path| path.to_string_lossy().contains(&version))
         .map(|mut path| {
-            path.push(format!("solc-{}", &version));
+            path.push(format!("solc-{}{}", &version, std::env::consts::EXE_SUFFIX));
             path
         });
     Ok(path)
@@ -255,7 +473,8 @@ mod tests {
             install_blocking(&version).unwrap();
         }
         let res = find_installed_version_path(&version.to_string()).unwrap();
-        let expected = SVM_HOME.join(ver).join(format!("solc-{}", ver));
+        let expected =
+            SVM_HOME.join(ver).join(format!("solc-{}{}", ver, std::env::consts::EXE_SUFFIX));
         assert_eq!(res.unwrap(), expected);
     }
 
@@ -330,6 +549,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_supports_evm_version() {
+        let version = |v: &str| Version::parse(v).unwrap();
+        assert!(supports_evm_version(&version("0.8.7"), "london"));
+        assert!(!supports_evm_version(&version("0.8.6"), "london"));
+        assert!(supports_evm_version(&version("0.7.6"), "istanbul"));
+        assert!(!supports_evm_version(&version("0.7.6"), "berlin"));
+        assert!(!supports_evm_version(&version("0.4.14"), "homestead"));
+        assert!(supports_evm_version(&version("0.8.9"), "shanghai"));
+    }
+
     #[test]
     fn test_version_req() {
         let dir = mkdir();
@@ -425,6 +655,16 @@ mod tests {
         path
     }
 
+    #[test]
+    fn canonicalizes_paths() {
+        let dir = mkdir();
+        let path = canonicalize(dir.join("..").join("contracts")).unwrap();
+        assert!(Path::new(&path).is_absolute());
+        assert!(!path.contains('\\'));
+        assert!(path.ends_with("/contracts"));
+        rmdir(&dir);
+    }
+
     #[test]
     fn test_build_all_versions() {
         let path = get_glob("testdata/test-contract-versions");
@@ -432,6 +672,8 @@ mod tests {
         let res = builder.build_all().unwrap();
         // Contracts A to F
         assert_eq!(res.keys().collect::<Vec<_>>().len(), 5);
+        assert_eq!(builder.sources().len(), 5);
+        assert!(builder.sources()["B"].ends_with("/test-contract-versions/caret-0.4.14.sol"));
     }
 
     #[test]
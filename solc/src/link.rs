@@ -0,0 +1,113 @@
+//! Library linking. solc leaves a placeholder wherever the bytecode of a contract calls into
+//! a library, which has to be replaced with the library's address before deploying it.
+
+use ethers::{types::Address, utils::keccak256};
+use eyre::Result;
+
+/// The length of a placeholder in hex characters, i.e. the length of an address
+const PLACEHOLDER_LEN: usize = 40;
+
+/// A placeholder for the address of a library
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkReference {
+    /// The byte offset of the placeholder in the bytecode
+    pub offset: usize,
+    /// The name of the library
+    pub library: String,
+}
+
+/// The placeholders of a contract's creation and runtime bytecode
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkReferences {
+    pub bytecode: Vec<LinkReference>,
+    pub runtime_bytecode: Vec<LinkReference>,
+}
+
+impl LinkReferences {
+    /// The names of the libraries the contract has to be linked against
+    pub fn libraries(&self) -> impl Iterator<Item = &str> {
+        self.bytecode
+            .iter()
+            .chain(&self.runtime_bytecode)
+            .map(|reference| reference.library.as_str())
+    }
+}
+
+/// Decodes hex bytecode which may contain library placeholders, zeroing them. `libraries` are
+/// the `path:name` identifiers of the compiled contracts, which the placeholders refer to.
+pub fn decode_unlinked(code: &str, libraries: &[&str]) -> Result<(Vec<u8>, Vec<LinkReference>)> {
+    let mut code = code.trim_start_matches("0x").to_owned();
+    let mut references = Vec::new();
+    while let Some(start) = code.find("__") {
+        let placeholder = code
+            .get(start..start + PLACEHOLDER_LEN)
+            .ok_or_else(|| eyre::eyre!("truncated library placeholder at {}", start))?;
+        let library = resolve(placeholder, libraries)
+            .ok_or_else(|| eyre::eyre!("unknown library placeholder {}", placeholder))?;
+        references.push(LinkReference { offset: start / 2, library });
+        code.replace_range(start..start + PLACEHOLDER_LEN, &"0".repeat(PLACEHOLDER_LEN));
+    }
+    Ok((hex::decode(code)?, references))
+}
+
+/// Finds the library a placeholder refers to. Since 0.5.0 placeholders are `__$` followed by
+/// the hex of the first 17 bytes of the keccak of the library's `path:name` and `$__`. Before
+/// that, they were the `path:name` itself, truncated and padded with `_`.
+fn resolve(placeholder: &str, libraries: &[&str]) -> Option<String> {
+    let id = &placeholder[2..PLACEHOLDER_LEN - 2];
+    let library = if let Some(hash) = id.strip_prefix('$').and_then(|id| id.strip_suffix('$')) {
+        libraries.iter().find(|library| hex::encode(&keccak256(library.as_bytes())[..17]) == hash)
+    } else {
+        let id = id.trim_end_matches('_');
+        libraries.iter().find(|library| {
+            let truncated = library.get(..PLACEHOLDER_LEN - 4).unwrap_or(library);
+            truncated.trim_end_matches('_') == id
+        })
+    }?;
+    Some(library.rsplit_once(':').map(|(_, name)| name).unwrap_or(library).to_owned())
+}
+
+/// Fills the addresses of the libraries into the bytecode
+pub fn link(
+    bytecode: &mut [u8],
+    references: &[LinkReference],
+    address: impl Fn(&str) -> Option<Address>,
+) -> Result<()> {
+    for reference in references {
+        let address = address(&reference.library)
+            .ok_or_else(|| eyre::eyre!("library {} is not deployed", reference.library))?;
+        bytecode
+            .get_mut(reference.offset..reference.offset + 20)
+            .ok_or_else(|| eyre::eyre!("link reference out of bounds"))?
+            .copy_from_slice(address.as_bytes());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_libraries() {
+        let library = "src/Math.sol:Math";
+        let hash = hex::encode(&keccak256(library.as_bytes())[..17]);
+        let code = format!("0x6073__${}$__6000", hash);
+
+        let (mut bytecode, references) = decode_unlinked(&code, &[library]).unwrap();
+        assert_eq!(references, vec![LinkReference { offset: 2, library: "Math".to_owned() }]);
+        assert_eq!(bytecode.len(), 2 + 20 + 2);
+
+        let address = Address::repeat_byte(0xaa);
+        link(&mut bytecode, &references, |name| (name == "Math").then(|| address)).unwrap();
+        assert_eq!(&bytecode[2..22], address.as_bytes());
+        assert!(link(&mut bytecode, &references, |_| None).is_err());
+
+        // pre-0.5.0 placeholders
+        let code = format!("60{:_<40}", "__src/Math.sol:Math");
+        let (_, references) = decode_unlinked(&code, &[library]).unwrap();
+        assert_eq!(references[0].library, "Math");
+
+        assert!(decode_unlinked("6073__$abc", &[library]).is_err());
+    }
+}
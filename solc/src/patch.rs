@@ -0,0 +1,141 @@
+//! Span-based source editing, e.g. for codemods which locate what to change through the AST.
+//! Edits only touch their span, so the rest of the source keeps its formatting.
+
+use crate::ast;
+use eyre::Result;
+use serde_json::Value;
+
+/// A byte range of a source file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// Parses the `start:length:fileIndex` source location of AST nodes
+    pub fn from_src(src: &str) -> Result<Self> {
+        let mut parts = src.split(':');
+        let mut next = || -> Result<usize> {
+            Ok(parts
+                .next()
+                .ok_or_else(|| eyre::eyre!("invalid source location {}", src))?
+                .parse()?)
+        };
+        Ok(Self { start: next()?, len: next()? })
+    }
+
+    /// Returns the span of an AST node
+    pub fn of(node: &Value) -> Option<Self> {
+        node.get("src").and_then(Value::as_str).and_then(|src| Self::from_src(src).ok())
+    }
+}
+
+/// Replaces a span of the source with a new text. An empty span inserts the text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Patch {
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Patch {
+    pub fn replace(span: Span, replacement: impl Into<String>) -> Self {
+        Self { span, replacement: replacement.into() }
+    }
+
+    pub fn insert(offset: usize, text: impl Into<String>) -> Self {
+        Self::replace(Span { start: offset, len: 0 }, text)
+    }
+}
+
+/// Applies the patches to the source. Spans refer to the original source, so the patches can
+/// be provided in any order. Insertions at the same offset are applied in the provided order.
+pub fn apply(source: &str, mut patches: Vec<Patch>) -> Result<String> {
+    patches.sort_by_key(|patch| patch.span.start);
+
+    let mut out = String::with_capacity(source.len());
+    let mut offset = 0;
+    for patch in patches {
+        let span = patch.span;
+        if span.start < offset {
+            eyre::bail!("overlapping patches at {}", span.start)
+        }
+        let replaced = source
+            .get(span.start..span.end())
+            .ok_or_else(|| eyre::eyre!("span {:?} is out of bounds", span))?;
+        out.push_str(&source[offset..span.start]);
+        out.push_str(&patch.replacement);
+        offset = span.start + replaced.len();
+    }
+    out.push_str(&source[offset..]);
+    Ok(out)
+}
+
+/// Returns the patch adding an SPDX license header to the source, if it does not have one
+pub fn spdx_header(source: &str, license: &str) -> Option<Patch> {
+    if source.contains("SPDX-License-Identifier:") {
+        return None
+    }
+    Some(Patch::insert(0, format!("// SPDX-License-Identifier: {}\n", license)))
+}
+
+/// Returns the patches replacing the version requirement of all `pragma solidity` directives
+/// in the AST, e.g. with `^0.8.0`
+pub fn pragma_solidity(ast: &Value, version: &str) -> Vec<Patch> {
+    let mut patches = Vec::new();
+    ast::walk(ast, &mut |node| {
+        let is_solidity = node["literals"][0] == "solidity";
+        if ast::node_type(node) == Some("PragmaDirective") && is_solidity {
+            if let Some(span) = Span::of(node) {
+                patches.push(Patch::replace(span, format!("pragma solidity {};", version)));
+            }
+        }
+    });
+    patches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SOURCE: &str = "pragma solidity 0.7.6;\n\ncontract Foo {}\n";
+
+    #[test]
+    fn applies_patches() {
+        let patches = vec![
+            Patch::replace(Span { start: 33, len: 3 }, "Bar"),
+            Patch::insert(0, "// a\n"),
+            Patch::insert(0, "// b\n"),
+        ];
+        assert_eq!(
+            apply(SOURCE, patches).unwrap(),
+            "// a\n// b\npragma solidity 0.7.6;\n\ncontract Bar {}\n"
+        );
+
+        let overlapping =
+            vec![Patch::replace(Span { start: 0, len: 6 }, ""), Patch::insert(3, "x")];
+        assert!(apply(SOURCE, overlapping).is_err());
+        assert!(apply(SOURCE, vec![Patch::insert(100, "x")]).is_err());
+    }
+
+    #[test]
+    fn codemods() {
+        let with_header = apply(SOURCE, spdx_header(SOURCE, "MIT").into_iter().collect()).unwrap();
+        assert!(with_header.starts_with("// SPDX-License-Identifier: MIT\npragma"));
+        assert!(spdx_header(&with_header, "MIT").is_none());
+
+        let ast = json!({ "nodeType": "SourceUnit", "src": "0:41:0", "nodes": [
+            { "nodeType": "PragmaDirective", "src": "0:22:0", "literals": ["solidity", "0.7", ".6"] },
+            { "nodeType": "PragmaDirective", "src": "0:0:0", "literals": ["abicoder", "v2"] },
+        ]});
+        let patches = pragma_solidity(&ast, "^0.8.0");
+        assert_eq!(patches.len(), 1);
+        assert_eq!(Span::from_src("0:22:0").unwrap(), Span { start: 0, len: 22 });
+        assert_eq!(apply(SOURCE, patches).unwrap(), "pragma solidity ^0.8.0;\n\ncontract Foo {}\n");
+    }
+}
@@ -1,10 +1,10 @@
 use ethers_core::{
-    abi::{self, parse_abi, Function, ParamType, Token, Tokenizable},
+    abi::{self, parse_abi, Abi, Function, ParamType, Token, Tokenizable},
     types::*,
 };
 use eyre::Result;
-use rustc_hex::FromHex;
-use std::str::FromStr;
+use rustc_hex::{FromHex, ToHex};
+use std::{path::Path, str::FromStr};
 
 const BASE_TX_COST: u64 = 21000;
 
@@ -21,8 +21,66 @@ pub fn remove_extra_costs(gas: U256, calldata: &[u8]) -> U256 {
     gas - calldata_cost - BASE_TX_COST
 }
 
-pub fn decode_revert(error: &[u8]) -> Result<String> {
-    Ok(abi::decode(&[abi::ParamType::String], &error[4..])?[0].to_string())
+/// selector of `Error(string)`
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// selector of `Panic(uint256)`
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Decodes the revert data of a call: `Error(string)` reasons, `Panic(uint256)` codes, and
+/// the custom errors declared in the provided ABI
+pub fn decode_revert(error: &[u8], abi: Option<&Abi>) -> Result<String> {
+    if error.len() < 4 {
+        eyre::bail!("revert data too short: 0x{}", error.to_hex::<String>())
+    }
+    let (selector, data) = error.split_at(4);
+
+    if selector == ERROR_SELECTOR {
+        return Ok(abi::decode(&[ParamType::String], data)?[0].to_string())
+    }
+
+    if selector == PANIC_SELECTOR {
+        let code = abi::decode(&[ParamType::Uint(256)], data)?[0]
+            .clone()
+            .into_uint()
+            .ok_or_else(|| eyre::eyre!("invalid panic code"))?;
+        return Ok(match panic_reason(code) {
+            Some(reason) => format!("panic: {} (0x{:02x})", reason, code),
+            None => format!("panic: 0x{:02x}", code),
+        })
+    }
+
+    let custom = abi
+        .into_iter()
+        .flat_map(|abi| abi.errors())
+        .find(|custom| custom.signature()[..4] == *selector)
+        .ok_or_else(|| eyre::eyre!("unknown error selector 0x{}", selector.to_hex::<String>()))?;
+    let args = custom.decode(data)?.into_iter().map(|token| match token {
+        Token::Address(address) => format!("{:?}", address),
+        Token::Uint(num) => num.to_string(),
+        Token::String(s) => format!("{:?}", s),
+        token => token.to_string(),
+    });
+    Ok(format!("{}({})", custom.name, args.collect::<Vec<_>>().join(", ")))
+}
+
+/// The description of the Solidity panic codes
+fn panic_reason(code: U256) -> Option<&'static str> {
+    if code > U256::from(u8::MAX) {
+        return None
+    }
+    Some(match code.as_u32() {
+        0x00 => "generic compiler inserted panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic underflow or overflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to a zero-initialized internal function",
+        _ => return None,
+    })
 }
 
 pub fn to_table(value: serde_json::Value) -> String {
@@ -108,3 +166,72 @@ pub fn encode_args(func: &Function, args: &[String]) -> Result<Vec<u8>> {
     }
     Ok(func.encode_input(&inputs)?)
 }
+
+/// Loads `KEY=VALUE` pairs from a `.env` file into the process environment, so that RPC URLs
+/// and API keys can be kept out of the shell history. Variables which are already set take
+/// precedence over the file, and CLI arguments take precedence over both. A missing file is
+/// not an error.
+pub fn load_dotenv(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    if !path.is_file() {
+        return Ok(())
+    }
+
+    let contents = std::fs::read_to_string(path)?;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| eyre::eyre!("invalid line in {}: {}", path.display(), line))?;
+        let key = key.trim();
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+
+        if std::env::var_os(key).is_none() {
+            std::env::set_var(key, value);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_reverts() {
+        let encode = |selector: &[u8], tokens: &[Token]| [selector, &abi::encode(tokens)].concat();
+
+        let error = encode(&ERROR_SELECTOR, &[Token::String("not equal".to_owned())]);
+        assert_eq!(decode_revert(&error, None).unwrap(), "not equal");
+
+        let panic = encode(&PANIC_SELECTOR, &[Token::Uint(0x11.into())]);
+        assert_eq!(
+            decode_revert(&panic, None).unwrap(),
+            "panic: arithmetic underflow or overflow (0x11)"
+        );
+
+        let abi = Abi::load(
+            &br#"[{"type":"error","name":"Unauthorized","inputs":[{"name":"who","type":"address"},{"name":"amount","type":"uint256"}]}]"#[..],
+        )
+        .unwrap();
+        let custom = encode(
+            &ethers_core::utils::id("Unauthorized(address,uint256)"),
+            &[Token::Address(Address::zero()), Token::Uint(5.into())],
+        );
+        assert_eq!(
+            decode_revert(&custom, Some(&abi)).unwrap(),
+            "Unauthorized(0x0000000000000000000000000000000000000000, 5)"
+        );
+        assert!(decode_revert(&custom, None).is_err());
+    }
+}